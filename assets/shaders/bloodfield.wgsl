@@ -13,8 +13,15 @@ struct VertexOutput {
 };
 
 struct Input {
+    base_color: vec4<f32>;
+    highlight_color: vec4<f32>;
     time: f32;
     seed: f32;
+    density: f32;
+    speed: f32;
+    pulse: f32;
+    // 0 mix, 1 add, 2 multiply, 3 screen
+    blend_mode: u32;
 };
 
 [[group(0), binding(0)]]
@@ -62,17 +69,29 @@ fn sm_vr(st: vec2<f32>, time: f32) -> f32 {
 
 [[stage(fragment)]]
 fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    let coords = in.uv.xy * 20.0;
+    let coords = in.uv.xy * input.density;
 
     var c = 0.0;
     for (var i = 3.0; i >= 0.0; i = i - 1.0)   {
-        var vr = sm_vr(coords * pow(2.0, i), input.time/2.0 + input.seed);
+        var vr = sm_vr(coords * pow(2.0, i), input.time * input.speed + input.seed);
         vr = smoothStep(0.5, 1.5 , vr);
         c = mix(c, vr, 1.0 - smoothStep(0.4, 0.5, vr));
         c = (c + 0.325) * (1.0 - i * 0.1);
 
     }
-    let color = vec3<f32>(0.3, 0.01, 0.01) * (c);
+    c = c * (1.0 + input.pulse * sin(input.time * 3.0));
+
+    let base = input.base_color.rgb;
+    let highlight = input.highlight_color.rgb * c;
+
+    var color = mix(base, input.highlight_color.rgb, c);
+    if (input.blend_mode == 1u) {
+        color = base + highlight;
+    } else if (input.blend_mode == 2u) {
+        color = base * highlight;
+    } else if (input.blend_mode == 3u) {
+        color = 1.0 - (1.0 - base) * (1.0 - highlight);
+    }
 
     return vec4<f32>(color, 1.0);
 
@@ -0,0 +1,124 @@
+// Port of fire.frag, the texture array layers are replaced by octaves of noise
+// so the material needs nothing but its uniforms.
+
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+struct Input {
+    base_color: vec4<f32>;
+    flame_height: f32;
+    distortion_level: f32;
+    bottom_threshold: f32;
+    // 0 at rest, 1 at full speed
+    intensity: f32;
+};
+
+struct Globals {
+    time: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> input: Input;
+
+[[group(1), binding(1)]]
+var<uniform> globals: Globals;
+
+[[group(2), binding(0)]]
+var<uniform> mesh: Mesh;
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_position = mesh.model * vec4<f32>(vertex.position, 1.0);
+    var out: VertexOutput;
+    out.uv = vertex.uv;
+    out.clip_position = view.view_proj * world_position;
+    return out;
+}
+
+// Cellular and simple noise from https://thebookofshaders.com/12/
+
+fn random2(p: vec2<f32>) -> vec2<f32> {
+    return fract(
+        sin(vec2<f32>(dot(p, vec2<f32>(127.1, 311.7)), dot(p, vec2<f32>(269.5, 183.3))))
+        * 43758.5453
+    );
+}
+
+fn cellular_noise(uv: vec2<f32>, scale: f32) -> f32 {
+    let st = uv * scale;
+    let i_st = floor(st);
+    let f_st = fract(st);
+
+    var min_dist = 1.0;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let neighbor = vec2<f32>(f32(x), f32(y));
+            let point = random2(i_st + neighbor);
+            min_dist = min(min_dist, length(neighbor + point - f_st));
+        }
+    }
+    return min_dist;
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(1e4 * sin(17.0 * p.x + p.y * 0.1) * (0.1 + abs(sin(p.y * 13.0 + p.x))));
+}
+
+fn simple_noise(uv: vec2<f32>, scale: f32) -> f32 {
+    let st = uv * scale;
+    let i = floor(st);
+    let f = fract(st);
+
+    let a = hash(i);
+    let b = hash(i + vec2<f32>(1.0, 0.0));
+    let c = hash(i + vec2<f32>(0.0, 1.0));
+    let d = hash(i + vec2<f32>(1.0, 1.0));
+
+    let u = f * f * (3.0 - 2.0 * f);
+    return mix(a, b, u.x) + (c - a) * u.y * (1.0 - u.x) + (d - b) * u.x * u.y;
+}
+
+fn animate_vertically(uv: vec2<f32>, factor: f32) -> vec2<f32> {
+    return uv + vec2<f32>(0.0, factor * globals.time);
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let distortion = input.distortion_level;
+    let flame_height = input.flame_height * (1.0 + input.intensity);
+
+    let secondary_noise = simple_noise(animate_vertically(in.uv, 0.5), distortion);
+    let cellular_uv = mix(animate_vertically(in.uv, 0.25), vec2<f32>(secondary_noise), vec2<f32>(0.5));
+    let cellular = cellular_noise(cellular_uv, distortion);
+    let main_noise = simple_noise(animate_vertically(in.uv, 0.3), distortion);
+
+    let total_noise = main_noise * cellular;
+    let flame_uv = mix(in.uv, vec2<f32>(total_noise), vec2<f32>(0.0, flame_height))
+        + vec2<f32>(0.0, flame_height);
+
+    var image = 0.0;
+    for (var layer = 0; layer < 3; layer = layer + 1) {
+        let scale = distortion * (2.0 + f32(layer) * 2.0);
+        image = image + simple_noise(animate_vertically(flame_uv, 0.4 + f32(layer) * 0.2), scale);
+    }
+    image = image / 3.0;
+
+    // Fade the flames out towards the bottom of the mesh
+    let bottom = 0.5 * (1.0 - pow(in.uv.y, 2.0) + input.bottom_threshold);
+    let flame = clamp(image - clamp(bottom, 0.0, 1.0) + 0.5, 0.0, 1.0);
+
+    let ember = input.base_color.rgb * 0.15;
+    let glow = input.base_color.rgb * (2.0 + 4.0 * input.intensity);
+    return vec4<f32>(mix(ember, glow, flame), 1.0);
+}
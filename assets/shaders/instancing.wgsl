@@ -0,0 +1,275 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv: vec2<f32>;
+
+    // Translation in xyz, uniform scale in w
+    [[location(3)]] i_translation_scale: vec4<f32>;
+    [[location(4)]] i_rotation: vec4<f32>;
+    [[location(5)]] i_color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec4<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> mesh: Mesh;
+
+fn rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let local = rotate(vertex.i_rotation, vertex.position * vertex.i_translation_scale.w)
+        + vertex.i_translation_scale.xyz;
+    let world_position = mesh.model * vec4<f32>(local, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * world_position;
+    out.world_position = world_position;
+    out.world_normal = (mesh.model * vec4<f32>(rotate(vertex.i_rotation, vertex.normal), 0.0)).xyz;
+    out.color = vertex.i_color;
+    return out;
+}
+
+// Lighting below is bevy_pbr's `pbr.wgsl` (0.7) for a `StandardMaterial` with its default
+// roughness, metallic and reflectance, which is what the rings used before they were
+// instanced. That file can't be imported, so the functions are copied as they are. The rings
+// don't receive shadows, none of the shaft's lights cast any.
+
+let PERCEPTUAL_ROUGHNESS: f32 = 0.089;
+let METALLIC: f32 = 0.01;
+let REFLECTANCE: f32 = 0.5;
+
+let PI: f32 = 3.141592653589793;
+
+fn saturate(value: f32) -> f32 {
+    return clamp(value, 0.0, 1.0);
+}
+
+fn getDistanceAttenuation(distanceSquare: f32, inverseRangeSquared: f32) -> f32 {
+    let factor = distanceSquare * inverseRangeSquared;
+    let smoothFactor = saturate(1.0 - factor * factor);
+    let attenuation = smoothFactor * smoothFactor;
+    return attenuation * 1.0 / max(distanceSquare, 0.0001);
+}
+
+fn D_GGX(roughness: f32, NoH: f32, h: vec3<f32>) -> f32 {
+    let oneMinusNoHSquared = 1.0 - NoH * NoH;
+    let a = NoH * roughness;
+    let k = roughness / (oneMinusNoHSquared + a * a);
+    let d = k * k * (1.0 / PI);
+    return d;
+}
+
+fn V_SmithGGXCorrelated(roughness: f32, NoV: f32, NoL: f32) -> f32 {
+    let a2 = roughness * roughness;
+    let lambdaV = NoL * sqrt((NoV - a2 * NoV) * NoV + a2);
+    let lambdaL = NoV * sqrt((NoL - a2 * NoL) * NoL + a2);
+    let v = 0.5 / (lambdaV + lambdaL);
+    return v;
+}
+
+fn F_Schlick_vec(f0: vec3<f32>, f90: f32, VoH: f32) -> vec3<f32> {
+    return f0 + (f90 - f0) * pow(1.0 - VoH, 5.0);
+}
+
+fn F_Schlick(f0: f32, f90: f32, VoH: f32) -> f32 {
+    return f0 + (f90 - f0) * pow(1.0 - VoH, 5.0);
+}
+
+fn fresnel(f0: vec3<f32>, LoH: f32) -> vec3<f32> {
+    let f90 = saturate(dot(f0, vec3<f32>(50.0 * 0.33)));
+    return F_Schlick_vec(f0, f90, LoH);
+}
+
+fn specular(f0: vec3<f32>, roughness: f32, h: vec3<f32>, NoV: f32, NoL: f32,
+              NoH: f32, LoH: f32, specularIntensity: f32) -> vec3<f32> {
+    let D = D_GGX(roughness, NoH, h);
+    let V = V_SmithGGXCorrelated(roughness, NoV, NoL);
+    let F = fresnel(f0, LoH);
+
+    return (specularIntensity * D * V) * F;
+}
+
+fn Fd_Burley(roughness: f32, NoV: f32, NoL: f32, LoH: f32) -> f32 {
+    let f90 = 0.5 + 2.0 * roughness * LoH * LoH;
+    let lightScatter = F_Schlick(1.0, f90, NoL);
+    let viewScatter = F_Schlick(1.0, f90, NoV);
+    return lightScatter * viewScatter * (1.0 / PI);
+}
+
+fn EnvBRDFApprox(f0: vec3<f32>, perceptual_roughness: f32, NoV: f32) -> vec3<f32> {
+    let c0 = vec4<f32>(-1.0, -0.0275, -0.572, 0.022);
+    let c1 = vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let r = perceptual_roughness * c0 + c1;
+    let a004 = min(r.x * r.x, exp2(-9.28 * NoV)) * r.x + r.y;
+    let AB = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return f0 * AB.x + AB.y;
+}
+
+fn perceptualRoughnessToRoughness(perceptualRoughness: f32) -> f32 {
+    let clampedPerceptualRoughness = clamp(perceptualRoughness, 0.089, 1.0);
+    return clampedPerceptualRoughness * clampedPerceptualRoughness;
+}
+
+fn luminance(v: vec3<f32>) -> f32 {
+    return dot(v, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn change_luminance(c_in: vec3<f32>, l_out: f32) -> vec3<f32> {
+    let l_in = luminance(c_in);
+    return c_in * (l_out / l_in);
+}
+
+fn reinhard_luminance(color: vec3<f32>) -> vec3<f32> {
+    let l_old = luminance(color);
+    let l_new = l_old / (1.0 + l_old);
+    return change_luminance(color, l_new);
+}
+
+fn view_z_to_z_slice(view_z: f32, is_orthographic: bool) -> u32 {
+    var z_slice: u32 = 0u;
+    if (is_orthographic) {
+        z_slice = u32(floor((view_z - lights.cluster_factors.z) * lights.cluster_factors.w));
+    } else {
+        z_slice = u32(log(-view_z) * lights.cluster_factors.z - lights.cluster_factors.w + 1.0);
+    }
+    return min(z_slice, lights.cluster_dimensions.z - 1u);
+}
+
+fn fragment_cluster_index(frag_coord: vec2<f32>, view_z: f32, is_orthographic: bool) -> u32 {
+    let xy = vec2<u32>(floor(frag_coord * lights.cluster_factors.xy));
+    let z_slice = view_z_to_z_slice(view_z, is_orthographic);
+    return min(
+        (xy.y * lights.cluster_dimensions.x + xy.x) * lights.cluster_dimensions.z + z_slice,
+        lights.cluster_dimensions.w - 1u
+    );
+}
+
+// Has to match CLUSTER_COUNT_SIZE in bevy_pbr's light.rs
+let CLUSTER_COUNT_SIZE = 13u;
+fn unpack_offset_and_count(cluster_index: u32) -> vec2<u32> {
+#ifdef NO_STORAGE_BUFFERS_SUPPORT
+    let offset_and_count = cluster_offsets_and_counts.data[cluster_index >> 2u][cluster_index & ((1u << 2u) - 1u)];
+    return vec2<u32>(
+        (offset_and_count >> CLUSTER_COUNT_SIZE) & ((1u << 32u - CLUSTER_COUNT_SIZE) - 1u),
+        offset_and_count & ((1u << CLUSTER_COUNT_SIZE) - 1u)
+    );
+#else
+    return cluster_offsets_and_counts.data[cluster_index];
+#endif
+}
+
+fn get_light_id(index: u32) -> u32 {
+#ifdef NO_STORAGE_BUFFERS_SUPPORT
+    let indices = cluster_light_index_lists.data[index >> 4u][(index >> 2u) & ((1u << 2u) - 1u)];
+    return (indices >> (8u * (index & ((1u << 2u) - 1u)))) & ((1u << 8u) - 1u);
+#else
+    return cluster_light_index_lists.data[index];
+#endif
+}
+
+fn point_light(
+    world_position: vec3<f32>, light: PointLight, roughness: f32, NdotV: f32, N: vec3<f32>, V: vec3<f32>,
+    R: vec3<f32>, F0: vec3<f32>, diffuseColor: vec3<f32>
+) -> vec3<f32> {
+    let light_to_frag = light.position_radius.xyz - world_position.xyz;
+    let distance_square = dot(light_to_frag, light_to_frag);
+    let rangeAttenuation =
+        getDistanceAttenuation(distance_square, light.color_inverse_square_range.w);
+
+    let a = roughness;
+    let centerToRay = dot(light_to_frag, R) * R - light_to_frag;
+    let closestPoint = light_to_frag + centerToRay * saturate(light.position_radius.w * inverseSqrt(dot(centerToRay, centerToRay)));
+    let LspecLengthInverse = inverseSqrt(dot(closestPoint, closestPoint));
+    let normalizationFactor = a / saturate(a + (light.position_radius.w * 0.5 * LspecLengthInverse));
+    let specularIntensity = normalizationFactor * normalizationFactor;
+
+    var L: vec3<f32> = closestPoint * LspecLengthInverse;
+    var H: vec3<f32> = normalize(L + V);
+    var NoL: f32 = saturate(dot(N, L));
+    var NoH: f32 = saturate(dot(N, H));
+    var LoH: f32 = saturate(dot(L, H));
+
+    let specular_light = specular(F0, roughness, H, NdotV, NoL, NoH, LoH, specularIntensity);
+
+    L = normalize(light_to_frag);
+    H = normalize(L + V);
+    NoL = saturate(dot(N, L));
+    NoH = saturate(dot(N, H));
+    LoH = saturate(dot(L, H));
+
+    let diffuse = diffuseColor * Fd_Burley(roughness, NdotV, NoL, LoH);
+
+    return ((diffuse + specular_light) * light.color_inverse_square_range.rgb) * (rangeAttenuation * NoL);
+}
+
+fn directional_light(light: DirectionalLight, roughness: f32, NdotV: f32, normal: vec3<f32>, view: vec3<f32>, R: vec3<f32>, F0: vec3<f32>, diffuseColor: vec3<f32>) -> vec3<f32> {
+    let incident_light = light.direction_to_light.xyz;
+
+    let half_vector = normalize(incident_light + view);
+    let NoL = saturate(dot(normal, incident_light));
+    let NoH = saturate(dot(normal, half_vector));
+    let LoH = saturate(dot(incident_light, half_vector));
+
+    let diffuse = diffuseColor * Fd_Burley(roughness, NdotV, NoL, LoH);
+    let specularIntensity = 1.0;
+    let specular_light = specular(F0, roughness, half_vector, NdotV, NoL, NoH, LoH, specularIntensity);
+
+    return (specular_light + diffuse) * light.color.rgb * NoL;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let roughness = perceptualRoughnessToRoughness(PERCEPTUAL_ROUGHNESS);
+    let N = normalize(in.world_normal);
+
+    var V: vec3<f32>;
+    let is_orthographic = view.projection[3].w == 1.0;
+    if (is_orthographic) {
+        V = normalize(vec3<f32>(view.view_proj[0].z, view.view_proj[1].z, view.view_proj[2].z));
+    } else {
+        V = normalize(view.world_position.xyz - in.world_position.xyz);
+    }
+
+    let NdotV = max(dot(N, V), 0.0001);
+    let F0 = 0.16 * REFLECTANCE * REFLECTANCE * (1.0 - METALLIC) + in.color.rgb * METALLIC;
+    let diffuse_color = in.color.rgb * (1.0 - METALLIC);
+    let R = reflect(-V, N);
+
+    var light_accum: vec3<f32> = vec3<f32>(0.0);
+
+    let view_z = dot(vec4<f32>(
+        view.inverse_view[0].z,
+        view.inverse_view[1].z,
+        view.inverse_view[2].z,
+        view.inverse_view[3].z
+    ), in.world_position);
+    let cluster_index = fragment_cluster_index(in.clip_position.xy, view_z, is_orthographic);
+    let offset_and_count = unpack_offset_and_count(cluster_index);
+    for (var i: u32 = offset_and_count[0]; i < offset_and_count[0] + offset_and_count[1]; i = i + 1u) {
+        let light = point_lights.data[get_light_id(i)];
+        light_accum = light_accum + point_light(in.world_position.xyz, light, roughness, NdotV, N, V, R, F0, diffuse_color);
+    }
+
+    for (var i: u32 = 0u; i < lights.n_directional_lights; i = i + 1u) {
+        let light = lights.directional_lights[i];
+        light_accum = light_accum + directional_light(light, roughness, NdotV, N, V, R, F0, diffuse_color);
+    }
+
+    let diffuse_ambient = EnvBRDFApprox(diffuse_color, 1.0, NdotV);
+    let specular_ambient = EnvBRDFApprox(F0, PERCEPTUAL_ROUGHNESS, NdotV);
+    let color = light_accum + (diffuse_ambient + specular_ambient) * lights.ambient_color.rgb;
+
+    return vec4<f32>(reinhard_luminance(color), in.color.a);
+}
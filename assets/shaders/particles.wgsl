@@ -0,0 +1,98 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+struct Vertex {
+    // Spawn offset from the emitter
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] velocity: vec3<f32>;
+    // Quad corner, -1..1
+    [[location(2)]] corner: vec2<f32>;
+    // Lifetime phase and a random 0..1 seed
+    [[location(3)]] particle: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] corner: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+struct Input {
+    start_color: vec4<f32>;
+    end_color: vec4<f32>;
+    gravity: vec3<f32>;
+    start_time: f32;
+    lifetime: f32;
+    size: f32;
+    swirl: f32;
+    drag: f32;
+    looping: u32;
+};
+
+struct Globals {
+    time: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> input: Input;
+
+[[group(1), binding(1)]]
+var<uniform> globals: Globals;
+
+[[group(2), binding(0)]]
+var<uniform> mesh: Mesh;
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let phase = vertex.particle.x;
+    let seed = vertex.particle.y;
+
+    // Between half and one and a half times the effect lifetime
+    let lifetime = input.lifetime * (0.5 + seed);
+
+    let time = globals.time - input.start_time;
+
+    var age = time;
+    if (input.looping == 1u) {
+        age = fract(time / lifetime + phase) * lifetime;
+    }
+    let t = clamp(age / lifetime, 0.0, 1.0);
+
+    // Distance travelled under linear drag
+    var travel = age;
+    if (input.drag > 0.0) {
+        travel = (1.0 - exp(-input.drag * age)) / input.drag;
+    }
+    var position = vertex.position + vertex.velocity * travel + 0.5 * input.gravity * age * age;
+
+    let angle = input.swirl * age;
+    let c = cos(angle);
+    let s = sin(angle);
+    position = vec3<f32>(position.x * c - position.z * s, position.y, position.x * s + position.z * c);
+
+    // Dead particles collapse to nothing instead of being removed
+    var size = input.size * (1.0 - t) * (0.5 + seed);
+    if (age >= lifetime) {
+        size = 0.0;
+    }
+
+    // Billboard towards the camera
+    let center = mesh.model * vec4<f32>(position, 1.0);
+    let right = view.view[0].xyz;
+    let up = view.view[1].xyz;
+    let world_position = center.xyz + (right * vertex.corner.x + up * vertex.corner.y) * size;
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(world_position, 1.0);
+    out.corner = vertex.corner;
+    out.color = mix(input.start_color, input.end_color, t);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let falloff = 1.0 - smoothStep(0.2, 1.0, length(in.corner));
+    let alpha = in.color.a * falloff;
+    // Premultiplied for the additive blend
+    return vec4<f32>(in.color.rgb * alpha, alpha);
+}
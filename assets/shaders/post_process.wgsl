@@ -0,0 +1,75 @@
+#import bevy_sprite::mesh2d_view_bind_group
+#import bevy_sprite::mesh2d_struct
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+};
+
+struct Input {
+    blur: f32;
+    aberration: f32;
+    vignette: f32;
+    desaturation: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> view: View;
+
+[[group(1), binding(0)]]
+var<uniform> input: Input;
+[[group(1), binding(1)]]
+var source_texture: texture_2d<f32>;
+[[group(1), binding(2)]]
+var source_sampler: sampler;
+
+[[group(2), binding(0)]]
+var<uniform> mesh: Mesh2d;
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_position = mesh.model * vec4<f32>(vertex.position, 1.0);
+    var out: VertexOutput;
+    out.uv = vertex.uv;
+    out.clip_position = view.view_proj * world_position;
+    return out;
+}
+
+fn sample_aberrated(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let r = textureSample(source_texture, source_sampler, uv + offset).r;
+    let g = textureSample(source_texture, source_sampler, uv).g;
+    let b = textureSample(source_texture, source_sampler, uv - offset).b;
+    return vec3<f32>(r, g, b);
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let to_center = vec2<f32>(0.5, 0.5) - in.uv;
+    let distance = length(to_center);
+
+    // Chromatic aberration grows towards the edges of the screen
+    let offset = -to_center * input.aberration * 0.02;
+
+    // Radial blur, samples are pulled towards the centre
+    var color = vec3<f32>(0.0);
+    let samples = 8;
+    for (var i = 0; i < samples; i = i + 1) {
+        let scale = f32(i) / f32(samples) * input.blur * 0.08 * distance;
+        color = color + sample_aberrated(in.uv + to_center * scale, offset);
+    }
+    color = color / f32(samples);
+
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    color = mix(color, vec3<f32>(luminance), input.desaturation);
+
+    let vignette = smoothStep(0.25, 0.75, distance) * input.vignette;
+    color = mix(color, vec3<f32>(0.5, 0.0, 0.0), vignette);
+
+    return vec4<f32>(color, 1.0);
+}
@@ -93,6 +93,19 @@ struct BloodfieldMaterial {
     seed: f32,
 }
 
+// Mirrors the `Input` uniform of `bloodfield.wgsl`, with the menu look baked in
+#[derive(Clone, AsStd140)]
+struct BloodfieldUniform {
+    base_color: Vec4,
+    highlight_color: Vec4,
+    time: f32,
+    seed: f32,
+    density: f32,
+    speed: f32,
+    pulse: f32,
+    blend_mode: u32,
+}
+
 #[derive(Clone)]
 struct GpuBloodfieldMaterial {
     bind_group: BindGroup,
@@ -110,7 +123,16 @@ impl RenderAsset for BloodfieldMaterial {
         extracted_asset: Self::ExtractedAsset,
         (render_device, material_pipeline): &mut SystemParamItem<Self::Param>,
     ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
-        let value = Vec4::new(extracted_asset.time, extracted_asset.seed, 0.0, 0.0);
+        let value = BloodfieldUniform {
+            base_color: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            highlight_color: Vec4::new(0.3, 0.01, 0.01, 1.0),
+            time: extracted_asset.time,
+            seed: extracted_asset.seed,
+            density: 20.0,
+            speed: 0.5,
+            pulse: 0.0,
+            blend_mode: 0,
+        };
         let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
             contents: value.as_std140().as_bytes(),
             label: Some("Bloodfield Settings Buffer"),
@@ -149,7 +171,9 @@ impl Material2d for BloodfieldMaterial {
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(Vec4::std140_size_static() as u64),
+                    min_binding_size: BufferSize::new(
+                        BloodfieldUniform::std140_size_static() as u64
+                    ),
                 },
                 count: None,
             }],
@@ -0,0 +1,298 @@
+use bevy::prelude::*;
+
+use crate::app_states::*;
+use crate::audio::*;
+use crate::gameplay_events::*;
+use crate::saves::*;
+use crate::state_scoped::*;
+use crate::widgets::*;
+
+const ACHIEVEMENTS_SAVE: &str = "achievements.txt";
+
+// Components
+
+#[derive(Component)]
+pub struct Toast {
+    timer: Timer,
+}
+
+// Resources
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Achievement {
+    NoBrakes,
+    Overflowing,
+    SpeedOfSin,
+    Martyr,
+}
+
+impl Achievement {
+    pub const ALL: [Achievement; 4] = [
+        Achievement::NoBrakes,
+        Achievement::Overflowing,
+        Achievement::SpeedOfSin,
+        Achievement::Martyr,
+    ];
+
+    /// Name in the save file, never change these.
+    fn id(&self) -> &'static str {
+        match self {
+            Achievement::NoBrakes => "no_brakes",
+            Achievement::Overflowing => "overflowing",
+            Achievement::SpeedOfSin => "speed_of_sin",
+            Achievement::Martyr => "martyr",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Achievement::NoBrakes => "NO  BRAKES",
+            Achievement::Overflowing => "OVERFLOWING",
+            Achievement::SpeedOfSin => "SPEED  OF  SIN",
+            Achievement::Martyr => "MARTYR",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Achievement::NoBrakes => "finish  without  braking",
+            Achievement::Overflowing => "finish  with  more  than  150  health",
+            Achievement::SpeedOfSin => "reach  speed  300",
+            Achievement::Martyr => "survive  10  seconds  in  the  damage  zone",
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Achievements {
+    pub unlocked: Vec<Achievement>,
+}
+
+impl Achievements {
+    pub fn load() -> Self {
+        let contents = read_save(ACHIEVEMENTS_SAVE).unwrap_or_default();
+
+        let unlocked = contents
+            .lines()
+            .filter_map(|line| {
+                Achievement::ALL
+                    .into_iter()
+                    .find(|achievement| achievement.id() == line.trim())
+            })
+            .collect();
+
+        Self { unlocked }
+    }
+
+    pub fn save(&self) {
+        let contents: String = self
+            .unlocked
+            .iter()
+            .map(|achievement| format!("{}\n", achievement.id()))
+            .collect();
+
+        write_save(ACHIEVEMENTS_SAVE, &contents);
+    }
+
+    pub fn is_unlocked(&self, achievement: Achievement) -> bool {
+        self.unlocked.contains(&achievement)
+    }
+
+    /// Returns whether the achievement was newly unlocked.
+    fn unlock(&mut self, achievement: Achievement) -> bool {
+        if self.is_unlocked(achievement) {
+            return false;
+        }
+
+        self.unlocked.push(achievement);
+        self.save();
+        true
+    }
+}
+
+// Events
+
+pub(crate) struct AchievementUnlocked(pub Achievement);
+
+// GAMEPLAY VARIABLES
+
+const OVERFLOWING_HEALTH: f32 = 150.0;
+const SPEED_OF_SIN: f32 = 300.0;
+const MARTYR_SECONDS: f32 = 10.0;
+const TOAST_SECONDS: f32 = 4.0;
+
+// Systems
+
+fn sys_unlock_achievements(
+    mut achievements: ResMut<Achievements>,
+    mut run_finished: EventReader<RunFinished>,
+    mut top_speed: EventReader<TopSpeedReached>,
+    mut damage_zone: EventReader<DamageZoneLeft>,
+    mut unlocked: EventWriter<AchievementUnlocked>,
+) {
+    let mut earned = Vec::new();
+
+    // Only runs played start to finish in one go count, a late start or a checkpoint
+    // skips the part where it would have been hard
+    for event in run_finished.iter() {
+        if event.start_cycle > 0 || event.continued {
+            continue;
+        }
+
+        if !event.braked {
+            earned.push(Achievement::NoBrakes);
+        }
+        if event.health > OVERFLOWING_HEALTH {
+            earned.push(Achievement::Overflowing);
+        }
+    }
+
+    if top_speed.iter().any(|event| event.speed >= SPEED_OF_SIN) {
+        earned.push(Achievement::SpeedOfSin);
+    }
+
+    if damage_zone
+        .iter()
+        .any(|event| event.seconds >= MARTYR_SECONDS)
+    {
+        earned.push(Achievement::Martyr);
+    }
+
+    for achievement in earned {
+        if achievements.unlock(achievement) {
+            unlocked.send(AchievementUnlocked(achievement));
+        }
+    }
+}
+
+fn sys_spawn_toasts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    app_state: Res<State<AppState>>,
+    mut unlocked: EventReader<AchievementUnlocked>,
+    mut sounds: EventWriter<PlaySound>,
+    query_toasts: Query<&Toast>,
+) {
+    let font = asset_server.load(FONT);
+
+    for (i, AchievementUnlocked(achievement)) in unlocked.iter().enumerate() {
+        sounds.send(PlaySound::ui("music/click.mp3"));
+
+        // Stack below the toasts still on screen
+        let slot = query_toasts.iter().count() + i;
+
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        top: Val::Px(20.0 + 70.0 * slot as f32),
+                        right: Val::Px(20.0),
+                        ..Default::default()
+                    },
+                    padding: Rect::all(Val::Px(10.0)),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    ..Default::default()
+                },
+                color: Color::rgba(0.6, 0.1, 0.1, 0.9).into(),
+                ..Default::default()
+            })
+            .insert(Toast {
+                timer: Timer::from_seconds(TOAST_SECONDS, false),
+            })
+            .insert(StateScoped(app_state.current().clone()))
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        achievement.title(),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 25.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        achievement.description(),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 14.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            });
+    }
+}
+
+fn sys_update_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query_toasts: Query<(Entity, &mut Toast)>,
+) {
+    for (e, mut toast) in query_toasts.iter_mut() {
+        if toast.timer.tick(time.delta()).finished() {
+            commands.entity(e).despawn_recursive();
+        }
+    }
+}
+
+// Plugins
+
+pub struct AchievementsPlugin;
+impl Plugin for AchievementsPlugin {
+    fn build(&self, app: &mut App) {
+        // Gameplay events arrive in whatever state follows them, the last teleport goes
+        // straight to the end screen
+        app.insert_resource(Achievements::load())
+            .add_event::<AchievementUnlocked>()
+            .add_system(sys_unlock_achievements)
+            .add_system(sys_spawn_toasts)
+            .add_system(sys_update_toasts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+
+    use super::*;
+
+    fn finish_run(start_cycle: u8, continued: bool) -> Vec<Achievement> {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<RunFinished>()
+            .add_event::<TopSpeedReached>()
+            .add_event::<DamageZoneLeft>()
+            .add_event::<AchievementUnlocked>()
+            .init_resource::<Achievements>()
+            .add_system(sys_unlock_achievements);
+
+        app.world
+            .resource_mut::<Events<RunFinished>>()
+            .send(RunFinished {
+                health: OVERFLOWING_HEALTH + 10.0,
+                braked: false,
+                start_cycle,
+                continued,
+            });
+        app.update();
+
+        app.world.resource::<Achievements>().unlocked.clone()
+    }
+
+    #[test]
+    fn only_full_runs_finish_achievements() {
+        assert_eq!(finish_run(3, false), vec![]);
+        assert_eq!(finish_run(3, true), vec![]);
+        assert_eq!(
+            finish_run(0, false),
+            vec![Achievement::NoBrakes, Achievement::Overflowing]
+        );
+    }
+}
@@ -1,8 +1,20 @@
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub(crate) enum AppState {
+    Loading,
     MainMenu,
     FallingGame,
     GameOver,
     GameEnd,
     CutScene,
 }
+
+impl AppState {
+    pub const ALL: [AppState; 6] = [
+        AppState::Loading,
+        AppState::MainMenu,
+        AppState::FallingGame,
+        AppState::GameOver,
+        AppState::GameEnd,
+        AppState::CutScene,
+    ];
+}
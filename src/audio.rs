@@ -0,0 +1,322 @@
+use std::marker::PhantomData;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_kira_audio::{AudioApp, AudioChannel};
+use rand::Rng;
+
+use crate::app_states::*;
+use crate::settings::*;
+use crate::spatial_audio::*;
+use crate::state_scoped::*;
+
+// Channels
+
+pub struct MusicDeckA;
+pub struct MusicDeckB;
+pub struct UiChannel;
+/// One line at a time, so sound effects never cut off a scream or narration.
+pub struct VoiceChannel;
+/// Sound effects take turns on these, so each one can get its own pitch and panning.
+pub struct OneShotSlot<const N: usize>;
+
+const ONE_SHOT_SLOTS: usize = 4;
+
+// Events
+
+#[derive(Debug)]
+pub(crate) enum PlayMusic {
+    Track(&'static str),
+    Silence,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum SoundChannel {
+    Sfx,
+    Ui,
+    Voice,
+}
+
+pub(crate) struct PlaySound {
+    pub path: &'static str,
+    pub channel: SoundChannel,
+    pub position: Option<Vec3>,
+}
+
+impl PlaySound {
+    pub fn sfx(path: &'static str) -> Self {
+        Self {
+            path,
+            channel: SoundChannel::Sfx,
+            position: None,
+        }
+    }
+
+    pub fn ui(path: &'static str) -> Self {
+        Self {
+            path,
+            channel: SoundChannel::Ui,
+            position: None,
+        }
+    }
+
+    pub fn voice(path: &'static str) -> Self {
+        Self {
+            path,
+            channel: SoundChannel::Voice,
+            position: None,
+        }
+    }
+
+    pub fn at(mut self, position: Vec3) -> Self {
+        self.position = Some(position);
+        self
+    }
+}
+
+// Resources
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum MusicDeck {
+    A,
+    B,
+}
+
+#[derive(Debug)]
+pub(crate) struct MusicState {
+    pub playback_rate: f32,
+    track: Option<&'static str>,
+    deck: MusicDeck,
+    fade: f32,
+}
+
+impl Default for MusicState {
+    fn default() -> Self {
+        Self {
+            playback_rate: 1.0,
+            track: None,
+            deck: MusicDeck::A,
+            fade: 1.0,
+        }
+    }
+}
+
+#[derive(Default)]
+struct OneShotSlots {
+    next: usize,
+}
+
+#[derive(SystemParam)]
+struct OneShotChannels<'w, 's> {
+    slot_0: Res<'w, AudioChannel<OneShotSlot<0>>>,
+    slot_1: Res<'w, AudioChannel<OneShotSlot<1>>>,
+    slot_2: Res<'w, AudioChannel<OneShotSlot<2>>>,
+    slot_3: Res<'w, AudioChannel<OneShotSlot<3>>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+macro_rules! with_one_shot {
+    ($channels:expr, $slot:expr, $channel:ident => $body:expr) => {
+        match $slot {
+            0 => {
+                let $channel = &$channels.slot_0;
+                $body
+            }
+            1 => {
+                let $channel = &$channels.slot_1;
+                $body
+            }
+            2 => {
+                let $channel = &$channels.slot_2;
+                $body
+            }
+            _ => {
+                let $channel = &$channels.slot_3;
+                $body
+            }
+        }
+    };
+}
+
+// GAMEPLAY VARIABLES
+
+const CROSSFADE_SECONDS: f32 = 1.5;
+const MUSIC_VOLUME: f32 = 1.0;
+const SFX_PITCH_VARIATION: f32 = 0.08;
+const VOICE_PITCH_VARIATION: f32 = 0.15;
+const ONE_SHOT_RANGE: f32 = 60.0;
+
+// Systems
+
+fn start_track<T>(
+    channel: &AudioChannel<T>,
+    asset_server: &AssetServer,
+    track: Option<&'static str>,
+    playback_rate: f32,
+) {
+    channel.stop();
+    channel.set_volume(0.0);
+    channel.set_playback_rate(playback_rate);
+
+    if let Some(path) = track {
+        channel.play_looped(asset_server.load(path));
+    }
+}
+
+fn sys_crossfade_music(
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut music_events: EventReader<PlayMusic>,
+    mut state: ResMut<MusicState>,
+    settings: Res<Settings>,
+    deck_a: Res<AudioChannel<MusicDeckA>>,
+    deck_b: Res<AudioChannel<MusicDeckB>>,
+) {
+    for event in music_events.iter() {
+        let track = match event {
+            PlayMusic::Track(path) => Some(*path),
+            PlayMusic::Silence => None,
+        };
+
+        if track == state.track {
+            continue;
+        }
+
+        state.track = track;
+        state.fade = 0.0;
+        state.deck = match state.deck {
+            MusicDeck::A => MusicDeck::B,
+            MusicDeck::B => MusicDeck::A,
+        };
+
+        match state.deck {
+            MusicDeck::A => start_track(&deck_a, &asset_server, track, state.playback_rate),
+            MusicDeck::B => start_track(&deck_b, &asset_server, track, state.playback_rate),
+        }
+    }
+
+    if state.fade < 1.0 || settings.is_changed() {
+        state.fade = (state.fade + time.delta_seconds() / CROSSFADE_SECONDS).min(1.0);
+
+        let (volume_a, volume_b) = match state.deck {
+            MusicDeck::A => (state.fade, 1.0 - state.fade),
+            MusicDeck::B => (1.0 - state.fade, state.fade),
+        };
+
+        let volume = MUSIC_VOLUME * settings.music_volume;
+        deck_a.set_volume(volume_a * volume);
+        deck_b.set_volume(volume_b * volume);
+
+        if state.fade >= 1.0 {
+            match state.deck {
+                MusicDeck::A => deck_b.stop(),
+                MusicDeck::B => deck_a.stop(),
+            }
+        }
+    }
+
+    if state.is_changed() {
+        deck_a.set_playback_rate(state.playback_rate);
+        deck_b.set_playback_rate(state.playback_rate);
+    }
+}
+
+fn sys_play_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    app_state: Res<State<AppState>>,
+    settings: Res<Settings>,
+    mut last_state: Local<Option<AppState>>,
+    mut sound_events: EventReader<PlaySound>,
+    mut slots: ResMut<OneShotSlots>,
+    one_shots: OneShotChannels,
+    ui: Res<AudioChannel<UiChannel>>,
+    voice: Res<AudioChannel<VoiceChannel>>,
+) {
+    // Gameplay sounds do not outlive the screen that played them
+    if last_state.as_ref() != Some(app_state.current()) {
+        *last_state = Some(app_state.current().clone());
+        for slot in 0..ONE_SHOT_SLOTS {
+            with_one_shot!(one_shots, slot, channel => channel.stop());
+        }
+        voice.stop();
+    }
+
+    let mut rng = rand::thread_rng();
+
+    for event in sound_events.iter() {
+        match event.channel {
+            SoundChannel::Ui => {
+                ui.set_volume(settings.sound_volume);
+                ui.play(asset_server.load(event.path));
+                continue;
+            }
+            // Voices always play centred, a new line takes over from the last one
+            SoundChannel::Voice => {
+                voice.stop();
+                voice.set_playback_rate(
+                    1.0 + rng.gen_range(-VOICE_PITCH_VARIATION..VOICE_PITCH_VARIATION),
+                );
+                voice.set_volume(settings.voice_volume);
+                voice.play(asset_server.load(event.path));
+                continue;
+            }
+            SoundChannel::Sfx => {}
+        }
+
+        let playback_rate = 1.0 + rng.gen_range(-SFX_PITCH_VARIATION..SFX_PITCH_VARIATION);
+
+        // Positional sounds share the spatial slots with the emitters, so they pan as the
+        // listener moves
+        if let Some(position) = event.position {
+            commands
+                .spawn_bundle((
+                    Transform::from_translation(position),
+                    GlobalTransform::from_translation(position),
+                ))
+                .insert(AudioEmitter {
+                    sound: event.path,
+                    range: ONE_SHOT_RANGE,
+                    volume: 1.0,
+                    priority: 0,
+                })
+                .insert(OneShotEmitter::new(playback_rate))
+                .insert(StateScoped(app_state.current().clone()));
+            continue;
+        }
+
+        // The oldest one-shot gets cut off when all slots are busy
+        let slot = slots.next;
+        slots.next = (slots.next + 1) % ONE_SHOT_SLOTS;
+
+        with_one_shot!(one_shots, slot, channel => {
+            channel.stop();
+            channel.set_playback_rate(playback_rate);
+            channel.set_volume(settings.sound_volume);
+            channel.set_panning(0.5);
+            channel.play(asset_server.load(event.path));
+        });
+    }
+}
+
+// Plugins
+
+pub struct AudioManagerPlugin;
+impl Plugin for AudioManagerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<MusicDeckA>()
+            .add_audio_channel::<MusicDeckB>()
+            .add_audio_channel::<UiChannel>()
+            .add_audio_channel::<VoiceChannel>()
+            .add_audio_channel::<OneShotSlot<0>>()
+            .add_audio_channel::<OneShotSlot<1>>()
+            .add_audio_channel::<OneShotSlot<2>>()
+            .add_audio_channel::<OneShotSlot<3>>()
+            .add_event::<PlayMusic>()
+            .add_event::<PlaySound>()
+            .init_resource::<MusicState>()
+            .init_resource::<OneShotSlots>()
+            .add_system(sys_crossfade_music)
+            .add_system(sys_play_sounds);
+    }
+}
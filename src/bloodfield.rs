@@ -1,4 +1,5 @@
 use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
     core::FixedTimestep,
     ecs::system::{lifetimeless::SRes, SystemParamItem},
     pbr::MaterialPipeline,
@@ -14,12 +15,18 @@ use bevy::{
         },
         renderer::RenderDevice,
     },
+    utils::BoxedFuture,
 };
 
+use crate::saves::{field, parse_fields};
+use crate::shader_reload::BLOODFIELD_SHADER_HANDLE;
+
 pub struct BloodfieldPlugin;
 impl Plugin for BloodfieldPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(MaterialPlugin::<BloodfieldMaterial>::default())
+            .add_asset::<BloodfieldPreset>()
+            .init_asset_loader::<BloodfieldPresetLoader>()
             .add_system_set(
                 SystemSet::new()
                     .with_run_criteria(FixedTimestep::step(0.05))
@@ -31,18 +38,181 @@ impl Plugin for BloodfieldPlugin {
 #[allow(clippy::type_complexity)]
 fn update_bloodfield_material(
     time: Res<Time>,
+    presets: Res<Assets<BloodfieldPreset>>,
     mut bloodfield_materials: ResMut<Assets<BloodfieldMaterial>>,
 ) {
-    for (_id, mut bloodfield_material) in bloodfield_materials.iter_mut() {
+    for (_id, bloodfield_material) in bloodfield_materials.iter_mut() {
         bloodfield_material.time = time.seconds_since_startup() as f32;
+
+        // Presets are re-applied every tick so edits to the files show up live
+        if let Some(preset) = bloodfield_material
+            .preset
+            .as_ref()
+            .and_then(|handle| presets.get(handle))
+        {
+            preset.apply(bloodfield_material);
+        }
+    }
+}
+
+// Blend Modes
+
+/// How the animated highlight is combined with the base colour.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BloodfieldBlendMode {
+    #[default]
+    Mix,
+    Add,
+    Multiply,
+    Screen,
+}
+
+impl std::str::FromStr for BloodfieldBlendMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mix" => Ok(BloodfieldBlendMode::Mix),
+            "add" => Ok(BloodfieldBlendMode::Add),
+            "multiply" => Ok(BloodfieldBlendMode::Multiply),
+            "screen" => Ok(BloodfieldBlendMode::Screen),
+            _ => Err(()),
+        }
+    }
+}
+
+// Presets
+
+/// Named set of `BloodfieldMaterial` parameters, loaded from `.bloodfield` files.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "3F0B7C52-6E2D-4B8A-A1F4-9C5D2E7B8A61"]
+pub struct BloodfieldPreset {
+    pub base_color: Color,
+    pub highlight_color: Color,
+    pub density: f32,
+    pub speed: f32,
+    pub pulse: f32,
+    pub blend_mode: BloodfieldBlendMode,
+}
+
+impl BloodfieldPreset {
+    fn parse(contents: &str) -> Option<Self> {
+        let fields = parse_fields(contents);
+
+        let color = |key: &str| -> Option<Color> {
+            let value: String = field(&fields, key)?;
+            let channels: Vec<f32> = value
+                .split_whitespace()
+                .map(|channel| channel.parse().ok())
+                .collect::<Option<_>>()?;
+
+            match channels[..] {
+                [r, g, b] => Some(Color::rgb_linear(r, g, b)),
+                _ => None,
+            }
+        };
+
+        Some(Self {
+            base_color: color("base_color")?,
+            highlight_color: color("highlight_color")?,
+            density: field(&fields, "density")?,
+            speed: field(&fields, "speed")?,
+            pulse: field(&fields, "pulse")?,
+            blend_mode: field(&fields, "blend_mode")?,
+        })
+    }
+
+    fn apply(&self, material: &mut BloodfieldMaterial) {
+        material.base_color = self.base_color;
+        material.highlight_color = self.highlight_color;
+        material.density = self.density;
+        material.speed = self.speed;
+        material.pulse = self.pulse;
+        material.blend_mode = self.blend_mode;
+    }
+}
+
+#[derive(Default)]
+pub struct BloodfieldPresetLoader;
+
+impl AssetLoader for BloodfieldPresetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let contents = std::str::from_utf8(bytes)?;
+            let preset = BloodfieldPreset::parse(contents).ok_or_else(|| {
+                anyhow::anyhow!("invalid bloodfield preset {:?}", load_context.path())
+            })?;
+
+            load_context.set_default_asset(LoadedAsset::new(preset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bloodfield"]
     }
 }
 
-#[derive(Component, Debug, Clone, TypeUuid, Default)]
+// Material
+
+#[derive(Component, Debug, Clone, TypeUuid)]
 #[uuid = "AC784C13-7197-40AB-BC3A-2ADD64F9E242"]
 pub struct BloodfieldMaterial {
     pub time: f32,
     pub seed: f32,
+    pub base_color: Color,
+    pub highlight_color: Color,
+    /// Cells across the mesh UV range.
+    pub density: f32,
+    /// Animation speed, 1 is one unit of shader time per second.
+    pub speed: f32,
+    /// Strength of the brightness pulse, 0 disables it.
+    pub pulse: f32,
+    pub blend_mode: BloodfieldBlendMode,
+    /// Overrides the parameters above once loaded.
+    pub preset: Option<Handle<BloodfieldPreset>>,
+}
+
+impl Default for BloodfieldMaterial {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            seed: 0.0,
+            base_color: Color::rgb_linear(0.0, 0.0, 0.0),
+            highlight_color: Color::rgb_linear(0.3, 0.01, 0.01),
+            density: 20.0,
+            speed: 0.5,
+            pulse: 0.0,
+            blend_mode: BloodfieldBlendMode::Mix,
+            preset: None,
+        }
+    }
+}
+
+impl BloodfieldMaterial {
+    pub fn from_preset(preset: Handle<BloodfieldPreset>, seed: f32) -> Self {
+        Self {
+            seed,
+            preset: Some(preset),
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, AsStd140)]
+struct BloodfieldUniform {
+    base_color: Vec4,
+    highlight_color: Vec4,
+    time: f32,
+    seed: f32,
+    density: f32,
+    speed: f32,
+    pulse: f32,
+    blend_mode: u32,
 }
 
 #[derive(Clone)]
@@ -62,7 +232,16 @@ impl RenderAsset for BloodfieldMaterial {
         extracted_asset: Self::ExtractedAsset,
         (render_device, material_pipeline): &mut SystemParamItem<Self::Param>,
     ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
-        let value = Vec4::new(extracted_asset.time, extracted_asset.seed, 0.0, 0.0);
+        let value = BloodfieldUniform {
+            base_color: extracted_asset.base_color.as_linear_rgba_f32().into(),
+            highlight_color: extracted_asset.highlight_color.as_linear_rgba_f32().into(),
+            time: extracted_asset.time,
+            seed: extracted_asset.seed,
+            density: extracted_asset.density,
+            speed: extracted_asset.speed,
+            pulse: extracted_asset.pulse,
+            blend_mode: extracted_asset.blend_mode as u32,
+        };
         let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
             contents: value.as_std140().as_bytes(),
             label: Some("Bloodfield Settings Buffer"),
@@ -82,11 +261,11 @@ impl RenderAsset for BloodfieldMaterial {
 }
 
 impl Material for BloodfieldMaterial {
-    fn vertex_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
-        Some(asset_server.load("shaders/bloodfield.wgsl"))
+    fn vertex_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(BLOODFIELD_SHADER_HANDLE.typed())
     }
-    fn fragment_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
-        Some(asset_server.load("shaders/bloodfield.wgsl"))
+    fn fragment_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(BLOODFIELD_SHADER_HANDLE.typed())
     }
 
     fn bind_group(render_asset: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
@@ -101,7 +280,9 @@ impl Material for BloodfieldMaterial {
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(Vec4::std140_size_static() as u64),
+                    min_binding_size: BufferSize::new(
+                        BloodfieldUniform::std140_size_static() as u64
+                    ),
                 },
                 count: None,
             }],
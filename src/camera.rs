@@ -0,0 +1,234 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use heron::prelude::Velocity;
+
+use crate::app_states::*;
+use crate::settings::*;
+
+// Components
+
+/// Entity the game camera follows.
+#[derive(Component, Default)]
+pub struct CameraTarget;
+
+/// Visible body of the camera target, hidden while the camera sits inside it.
+#[derive(Component, Default)]
+pub struct CameraTargetModel;
+
+#[derive(Component, Debug)]
+pub struct GameCamera {
+    offset: Vec3,
+    rotation: Quat,
+    trauma: f32,
+}
+
+impl Default for GameCamera {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::ZERO,
+            rotation: top_down_rotation(),
+            trauma: 0.0,
+        }
+    }
+}
+
+// Events
+
+/// Shakes the game camera, `trauma` is added to the current shake and capped at 1.
+pub(crate) struct CameraShake {
+    pub trauma: f32,
+}
+
+// Resources
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub(crate) enum CameraMode {
+    #[default]
+    TopDown,
+    Chase,
+    Orbit,
+    Side,
+}
+
+impl CameraMode {
+    fn next(self) -> Self {
+        match self {
+            CameraMode::TopDown => CameraMode::Chase,
+            CameraMode::Chase => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Side,
+            CameraMode::Side => CameraMode::TopDown,
+        }
+    }
+
+    /// Only the cameras riding with the player shake, the outside views stay steady.
+    fn shakes(self) -> bool {
+        matches!(self, CameraMode::TopDown | CameraMode::Chase)
+    }
+}
+
+// GAMEPLAY VARIABLES
+
+const CAMERA_MODE_KEY: KeyCode = KeyCode::C;
+const BLEND_SPEED: f32 = 6.0;
+
+const CHASE_HEIGHT: f32 = 7.0;
+const CHASE_DISTANCE: f32 = 4.0;
+const ORBIT_RADIUS: f32 = 14.0;
+const ORBIT_HEIGHT: f32 = 4.0;
+const ORBIT_SPEED: f32 = 0.4;
+const SIDE_DISTANCE: f32 = 35.0;
+const SIDE_HEIGHT: f32 = 3.0;
+
+const BASE_FOV: f32 = std::f32::consts::PI / 4.0;
+const MAX_FOV: f32 = std::f32::consts::PI / 2.5;
+const FOV_MIN_SPEED: f32 = 30.0;
+const FOV_MAX_SPEED: f32 = 120.0;
+
+const SHAKE_DECAY: f32 = 1.5;
+const SHAKE_OFFSET: f32 = 0.6;
+const SHAKE_ROLL: f32 = 0.08;
+
+// Camera Modes
+
+fn top_down_rotation() -> Quat {
+    Quat::from_rotation_x(-std::f32::consts::PI / 2.0)
+}
+
+/// Camera offset from the target and camera rotation for `mode`.
+fn mode_pose(mode: CameraMode, target: &Transform, seconds: f32) -> (Vec3, Quat) {
+    let offset = match mode {
+        CameraMode::TopDown => return (Vec3::ZERO, target.rotation * top_down_rotation()),
+        CameraMode::Chase => target.rotation * Vec3::new(0.0, CHASE_HEIGHT, CHASE_DISTANCE),
+        CameraMode::Orbit => {
+            let angle = seconds * ORBIT_SPEED;
+            Vec3::new(
+                f32::cos(angle) * ORBIT_RADIUS,
+                ORBIT_HEIGHT,
+                f32::sin(angle) * ORBIT_RADIUS,
+            )
+        }
+        // Fixed outside the shaft, only following the target down
+        CameraMode::Side => Vec3::new(
+            -target.translation.x,
+            SIDE_HEIGHT,
+            SIDE_DISTANCE - target.translation.z,
+        ),
+    };
+
+    let rotation = Transform::from_translation(offset)
+        .looking_at(Vec3::ZERO, Vec3::Y)
+        .rotation;
+
+    (offset, rotation)
+}
+
+fn mode_fov(mode: CameraMode, speed: f32) -> f32 {
+    if mode != CameraMode::Chase {
+        return BASE_FOV;
+    }
+
+    let t = ((speed - FOV_MIN_SPEED) / (FOV_MAX_SPEED - FOV_MIN_SPEED)).clamp(0.0, 1.0);
+    BASE_FOV + (MAX_FOV - BASE_FOV) * t
+}
+
+// Systems
+
+fn sys_switch_camera_mode(keys: Res<Input<KeyCode>>, mut mode: ResMut<CameraMode>) {
+    if keys.just_pressed(CAMERA_MODE_KEY) {
+        *mode = mode.next();
+    }
+}
+
+fn sys_shake_camera(
+    settings: Res<Settings>,
+    mode: Res<CameraMode>,
+    mut shake_events: EventReader<CameraShake>,
+    mut query_camera: Query<&mut GameCamera>,
+) {
+    let trauma: f32 = shake_events.iter().map(|shake| shake.trauma).sum();
+    if trauma <= 0.0 || !settings.screen_shake || !mode.shakes() {
+        return;
+    }
+
+    for mut camera in query_camera.iter_mut() {
+        camera.trauma = (camera.trauma + trauma).min(1.0);
+    }
+}
+
+fn sys_follow_target(
+    time: Res<Time>,
+    mode: Res<CameraMode>,
+    query_target: Query<(&Transform, Option<&Velocity>), With<CameraTarget>>,
+    mut query_camera: Query<
+        (&mut Transform, &mut PerspectiveProjection, &mut GameCamera),
+        Without<CameraTarget>,
+    >,
+    mut query_model: Query<&mut Visibility, With<CameraTargetModel>>,
+) {
+    let (target, velocity) = match query_target.iter().next() {
+        Some(target) => target,
+        None => return,
+    };
+
+    let delta = time.delta_seconds();
+    let seconds = time.seconds_since_startup() as f32;
+    let speed = velocity.map_or(0.0, |v| v.linear.y.abs());
+
+    // Frame rate independent exponential smoothing
+    let blend = 1.0 - f32::exp(-BLEND_SPEED * delta);
+
+    let (offset, rotation) = mode_pose(*mode, target, seconds);
+    let fov = mode_fov(*mode, speed);
+
+    for (mut transform, mut projection, mut camera) in query_camera.iter_mut() {
+        // Interpolate relative to the target so the camera never lags behind the fall
+        camera.offset = camera.offset.lerp(offset, blend);
+        camera.rotation = camera.rotation.slerp(rotation, blend);
+        camera.trauma = match mode.shakes() {
+            true => (camera.trauma - SHAKE_DECAY * delta).max(0.0),
+            false => 0.0,
+        };
+
+        let shake = camera.trauma * camera.trauma;
+        let jitter = Vec3::new(
+            f32::sin(seconds * 37.0),
+            f32::sin(seconds * 41.0 + 1.3),
+            f32::sin(seconds * 43.0 + 2.7),
+        ) * SHAKE_OFFSET
+            * shake;
+        let roll = Quat::from_rotation_z(f32::sin(seconds * 29.0) * SHAKE_ROLL * shake);
+
+        transform.translation = target.translation + camera.offset + jitter;
+        transform.rotation = camera.rotation * roll;
+
+        if (projection.fov - fov).abs() > 0.001 {
+            projection.fov += (fov - projection.fov) * blend;
+        }
+
+        // The top-down camera sits inside the target
+        let model_visible = camera.offset.length() > 1.0;
+        for mut visibility in query_model.iter_mut() {
+            if visibility.is_visible != model_visible {
+                visibility.is_visible = model_visible;
+            }
+        }
+    }
+}
+
+// Plugins
+
+pub struct CameraModePlugin;
+impl Plugin for CameraModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraMode>()
+            .add_event::<CameraShake>()
+            .add_system_set(
+                SystemSet::on_update(AppState::FallingGame).with_system(sys_switch_camera_mode),
+            )
+            .add_system(sys_shake_camera)
+            // Physics has moved the target by now, following it any earlier makes it jitter
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                sys_follow_target.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
@@ -0,0 +1,54 @@
+use crate::endless::*;
+use crate::saves::*;
+
+const CHECKPOINT_SAVE: &str = "checkpoint.txt";
+
+// Resources
+
+#[derive(Default)]
+pub(crate) struct RunOptions {
+    pub continue_from_checkpoint: bool,
+    pub mode: RunMode,
+    /// Cycle a time attack starts from.
+    pub start_cycle: u8,
+}
+
+// Checkpoint
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Checkpoint {
+    pub cycle_number: u8,
+    pub seed: u64,
+    pub health: f32,
+    pub velocity: f32,
+    pub elapsed: f32,
+}
+
+impl Checkpoint {
+    pub fn load() -> Option<Self> {
+        let contents = read_save(CHECKPOINT_SAVE)?;
+        let fields = parse_fields(&contents);
+
+        Some(Self {
+            cycle_number: field(&fields, "cycle_number")?,
+            seed: field(&fields, "seed")?,
+            health: field(&fields, "health")?,
+            velocity: field(&fields, "velocity")?,
+            elapsed: field(&fields, "elapsed")?,
+        })
+    }
+
+    pub fn save(&self) {
+        write_save(
+            CHECKPOINT_SAVE,
+            &format!(
+                "cycle_number {}\nseed {}\nhealth {}\nvelocity {}\nelapsed {}\n",
+                self.cycle_number, self.seed, self.health, self.velocity, self.elapsed
+            ),
+        );
+    }
+
+    pub fn clear() {
+        remove_save(CHECKPOINT_SAVE);
+    }
+}
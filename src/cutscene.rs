@@ -3,16 +3,20 @@ use std::time::Duration;
 use bevy::{core::Stopwatch, prelude::*};
 
 use crate::app_states::AppState;
+use crate::state_scoped::*;
+use crate::widgets::*;
 
 // Components
 
 #[derive(Debug, Component)]
 pub(crate) struct CutsceneComponent;
 
-// Resourcesf
+// Resources
 
-pub(crate) struct CutsceneSettings {
-    pub next_stage: u8,
+#[derive(Default)]
+pub(crate) struct CutsceneRun {
+    next_stage: u8,
+    stopwatch: Stopwatch,
 }
 
 // Systems
@@ -20,21 +24,21 @@ pub(crate) struct CutsceneSettings {
 pub(crate) fn sys_show_scene(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut settings: ResMut<CutsceneSettings>,
+    mut run: ResMut<CutsceneRun>,
     mut query: Query<Entity, With<CutsceneComponent>>,
-    mut stopwatch: ResMut<Stopwatch>,
     time: Res<Time>,
     mut app_state: ResMut<State<AppState>>,
 ) {
-    if stopwatch.elapsed_secs() < 5.0 && settings.next_stage != 0 {
-        stopwatch.tick(Duration::from_secs_f32(time.delta_seconds()));
+    if run.stopwatch.elapsed_secs() < 5.0 && run.next_stage != 0 {
+        run.stopwatch
+            .tick(Duration::from_secs_f32(time.delta_seconds()));
         return;
     }
 
-    stopwatch.reset();
-    settings.next_stage += 1;
+    run.stopwatch.reset();
+    run.next_stage += 1;
 
-    if settings.next_stage == 6 {
+    if run.next_stage == 6 {
         app_state.set(AppState::FallingGame).unwrap();
         return;
     }
@@ -68,16 +72,17 @@ pub(crate) fn sys_show_scene(
 
     commands
         .spawn_bundle(UiCameraBundle::default())
-        .insert(CutsceneComponent);
+        .insert(CutsceneComponent)
+        .insert(StateScoped(AppState::CutScene));
 
     // Spawn items
 
     let text = Text::with_section(
-        &cutscene_items[(settings.next_stage - 1) as usize].0,
+        &cutscene_items[(run.next_stage - 1) as usize].0,
         TextStyle {
             color: Color::WHITE.into(),
             font_size: 75.0,
-            font: asset_server.load("fonts/ARCADECLASSIC.TTF"),
+            font: asset_server.load(FONT),
         },
         TextAlignment {
             horizontal: HorizontalAlign::Center,
@@ -85,19 +90,15 @@ pub(crate) fn sys_show_scene(
         },
     );
 
-    let img = &cutscene_items[(settings.next_stage - 1) as usize].1;
+    let img = &cutscene_items[(run.next_stage - 1) as usize].1;
 }
 
-fn sys_clear(
-    mut commands: Commands,
-    mut query: Query<Entity, With<CutsceneComponent>>,
-    // audio: Res<Audio>,
-) {
-    for q in query.iter_mut() {
-        commands.entity(q).despawn_recursive();
-    }
+fn sys_start_run(mut commands: Commands) {
+    commands.insert_resource(CutsceneRun::default());
+}
 
-    // audio.stop();
+fn sys_end_run(mut commands: Commands) {
+    commands.remove_resource::<CutsceneRun>();
 }
 
 // Plugins
@@ -105,7 +106,8 @@ fn sys_clear(
 pub struct CutscenePlugin;
 impl Plugin for CutscenePlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_exit(AppState::CutScene).with_system(sys_clear))
-            .add_system_set(SystemSet::on_update(AppState::CutScene).with_system(sys_show_scene));
+        app.add_system_set(SystemSet::on_enter(AppState::CutScene).with_system(sys_start_run))
+            .add_system_set(SystemSet::on_update(AppState::CutScene).with_system(sys_show_scene))
+            .add_system_set(SystemSet::on_exit(AppState::CutScene).with_system(sys_end_run));
     }
 }
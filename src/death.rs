@@ -0,0 +1,97 @@
+use crate::endless::*;
+use crate::saves::*;
+
+const DEATHS_SAVE: &str = "deaths.txt";
+
+// Resources
+
+/// What hurt the player last. Falling too fast is the only thing that hurts so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DeathCause {
+    TerminalVelocity,
+}
+
+impl DeathCause {
+    pub const ALL: [DeathCause; 1] = [DeathCause::TerminalVelocity];
+
+    /// Name in the save file, never change these.
+    fn id(&self) -> &'static str {
+        match self {
+            DeathCause::TerminalVelocity => "terminal_velocity",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            DeathCause::TerminalVelocity => "TERMINAL  VELOCITY",
+        }
+    }
+}
+
+/// Speed and health at one point of a run, for the graph on the game over screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RunSample {
+    pub speed: f32,
+    pub health: f32,
+}
+
+/// How a run ended, inserted by the falling game right before `GameOver`.
+pub(crate) struct DeathReport {
+    pub cause: DeathCause,
+    pub mode: RunMode,
+    pub cycle: u8,
+    pub depth: u32,
+    pub time: u32,
+    pub pickups: u32,
+    pub top_speed: f32,
+    pub history: Vec<RunSample>,
+}
+
+/// How many runs each cause has ended, over every run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct DeathStats {
+    pub counts: Vec<(DeathCause, u32)>,
+}
+
+impl DeathStats {
+    pub fn load() -> Self {
+        let contents = read_save(DEATHS_SAVE).unwrap_or_default();
+        let fields = parse_fields(&contents);
+
+        let counts = DeathCause::ALL
+            .into_iter()
+            .map(|cause| (cause, field(&fields, cause.id()).unwrap_or(0)))
+            .collect();
+
+        Self { counts }
+    }
+
+    pub fn save(&self) {
+        let contents: String = self
+            .counts
+            .iter()
+            .map(|(cause, count)| format!("{} {}\n", cause.id(), count))
+            .collect();
+
+        write_save(DEATHS_SAVE, &contents);
+    }
+
+    pub fn record(&mut self, cause: DeathCause) {
+        match self.counts.iter_mut().find(|(c, _)| *c == cause) {
+            Some((_, count)) => *count += 1,
+            None => self.counts.push((cause, 1)),
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    pub fn most_common(&self) -> Option<(DeathCause, u32)> {
+        self.counts
+            .iter()
+            .copied()
+            .filter(|(_, count)| *count > 0)
+            .max_by_key(|(_, count)| *count)
+    }
+}
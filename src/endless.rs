@@ -0,0 +1,59 @@
+use crate::shaft::*;
+
+// Resources
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum RunMode {
+    /// Eight cycles through the shaft, raced against the clock.
+    #[default]
+    Story,
+    /// The shaft never ends, it only gets harder the deeper you fall.
+    Endless,
+    /// The story raced against the best split of every cycle, from any unlocked cycle.
+    TimeAttack,
+}
+
+/// How hard a segment of the endless shaft is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Difficulty {
+    pub ring_speed: f32,
+    pub pickup_spacing: u32,
+    pub hidden_share: f32,
+    pub speed_share: f32,
+}
+
+impl Difficulty {
+    pub fn at_depth(depth: f32) -> Self {
+        let level = depth / SEGMENT_DEPTH;
+
+        Self {
+            ring_speed: (1.0 + RING_SPEED_PER_LEVEL * level).min(MAX_RING_SPEED),
+            pickup_spacing: (3.0 - level / 2.0).ceil().max(1.0) as u32,
+            hidden_share: (HIDDEN_SHARE_PER_LEVEL * level).min(MAX_HIDDEN_SHARE),
+            speed_share: (1.0 / 3.0 + SPEED_SHARE_PER_LEVEL * level).min(MAX_SPEED_SHARE),
+        }
+    }
+
+    /// Sets the shaft up for the next layout, see `Shaft::relayout`.
+    pub fn apply(&self, shaft: &mut Shaft) {
+        shaft.ring_speed = self.ring_speed;
+        shaft.ring_pattern = RingPattern::HiddenShare(self.hidden_share);
+        shaft.pickup_layout = PickupLayout {
+            spacing: self.pickup_spacing,
+            speed_share: Some(self.speed_share),
+        };
+    }
+}
+
+// GAMEPLAY VARIABLES
+
+/// Depth of one pass through the shaft, from the top to the teleport.
+pub(crate) const SEGMENT_DEPTH: f32 = SHAFT_FLOORS as f32 * FLOOR_HEIGHT;
+
+const RING_SPEED_PER_LEVEL: f32 = 0.35;
+const MAX_RING_SPEED: f32 = 4.0;
+const HIDDEN_SHARE_PER_LEVEL: f32 = 0.1;
+const MAX_HIDDEN_SHARE: f32 = 0.8;
+// Speed pickups are the ones that hurt, they push the player past the damage threshold
+const SPEED_SHARE_PER_LEVEL: f32 = 0.08;
+const MAX_SPEED_SHARE: f32 = 0.75;
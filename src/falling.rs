@@ -7,18 +7,32 @@ use rand::Rng;
 
 use bevy::core::FixedTimestep;
 use bevy::prelude::*;
-use bevy_kira_audio::Audio;
-use bevy_prototype_debug_lines::DebugLines;
 
 use heron::*;
 
 use crate::app_states::*;
+use crate::audio::*;
+use crate::bloodfield::*;
+use crate::camera::*;
+use crate::checkpoint::*;
+use crate::death::*;
+use crate::endless::*;
+use crate::fire::*;
 use crate::game_end::*;
+use crate::gameplay_events::*;
+use crate::hud::*;
 use crate::indoctrination::*;
+use crate::particles::*;
+use crate::post_process::*;
+use crate::score::*;
+use crate::shaft::*;
+use crate::spatial_audio::*;
+use crate::state_scoped::*;
+use crate::time_attack::*;
 
 // Components
 
-#[derive(PartialEq, Default, Debug)]
+#[derive(PartialEq, Default, Debug, Clone, Copy)]
 pub enum CubeType {
     #[default]
     Environment,
@@ -27,56 +41,35 @@ pub enum CubeType {
     Brake,
 }
 
+impl CubeType {
+    pub(crate) fn color(&self) -> Color {
+        match self {
+            CubeType::Brake => Color::rgba(0.7, 0.1, 0.1, 0.3),
+            CubeType::Health => Color::rgba(0.2, 0.7, 0.1, 0.3),
+            CubeType::Speed => Color::rgb(1.0, 0.3, 0.02),
+            CubeType::Environment => Color::WHITE,
+        }
+    }
+}
+
 #[derive(Component, Default, Debug)]
 pub struct Cube {
-    cube_type: CubeType,
+    pub(crate) cube_type: CubeType,
 }
 
 #[derive(Component, Default)]
 pub struct Teleport;
 
-#[derive(Component, Default)]
-pub struct Floor {
-    direction: u8,
-}
-
-#[derive(Component, Default)]
-pub struct FallingGameComponent;
-
 #[derive(Component, Default)]
 pub struct Chain;
 
-#[derive(Component, Default)]
-pub struct Interface;
-
-#[derive(Component, Default)]
-pub struct VelocityText;
-
-#[derive(Component, Default)]
-pub struct HealthText;
-
-#[derive(Component, Default)]
-pub struct DistanceText;
-
-#[derive(Component, Default)]
-pub struct StopwatchText;
-
 // Bundles
 
-#[derive(Bundle, Default)]
-struct CubeBundle {
-    #[bundle]
-    pbr_bundle: PbrBundle,
-    cube: Cube,
-    marker: FallingGameComponent,
-}
-
 #[derive(Bundle, Default)]
 struct TeleportBundle {
     #[bundle]
-    pbr_bundle: PbrBundle,
+    material_mesh_bundle: MaterialMeshBundle<FireMaterial>,
     teleport: Teleport,
-    marker: FallingGameComponent,
     collision_shape: CollisionShape,
     collision_layers: CollisionLayers,
     rigid_body: RigidBody,
@@ -84,7 +77,13 @@ struct TeleportBundle {
 
 #[derive(Component, Clone)]
 pub(crate) struct Actor {
-    health: f32,
+    pub(crate) health: f32,
+    /// Held brake this frame, see `control_player`.
+    pub(crate) braking: bool,
+    /// Braked at any point of the run.
+    braked: bool,
+    /// Time spent in the damage zone without leaving it.
+    damage_zone_time: f32,
     velocity: f32,
     scream_last_play: Option<std::time::Instant>,
 }
@@ -101,7 +100,7 @@ pub(crate) struct ActorBundle {
     transform: Transform,
     velocity: Velocity,
     physics_material: PhysicMaterial,
-    marker: FallingGameComponent,
+    scope: StateScoped,
 }
 
 fn new_actor_bundle() -> ActorBundle {
@@ -125,24 +124,106 @@ fn new_actor_bundle() -> ActorBundle {
         actor: Actor {
             scream_last_play: None,
             health: 100.0,
+            braking: false,
+            braked: false,
+            damage_zone_time: 0.0,
             velocity: 0.0,
         },
         rotation_constraints: RotationConstraints::lock(),
-        marker: FallingGameComponent,
+        scope: StateScoped(AppState::FallingGame),
     };
 }
 
 // Resources
 
-struct FallingState {
-    cycle_number: u8,
+/// Everything a single run of the falling game keeps track of. Inserted when the
+/// game is entered and removed on exit, so every run starts from a clean slate.
+#[derive(Default, Debug)]
+pub(crate) struct FallingRun {
+    pub(crate) cycle_number: u8,
+    /// Passes through the shaft, which an endless run keeps counting well past any cycle.
+    pub(crate) segment: u32,
+    pub(crate) mode: RunMode,
+    /// Deepest point reached over every cycle, the score of an endless run.
+    pub(crate) depth: f32,
+    pub(crate) splits: Vec<Split>,
+    pub(crate) score: Score,
+    top_speed: f32,
+    pickups: u32,
+    history: Vec<RunSample>,
+    since_sample: f32,
+    spark_cooldown: f32,
+    /// Damage taken since the camera last shook for it.
+    shake_damage: f32,
+    shake_cooldown: f32,
+    dead: bool,
+    seed: u64,
+    restored: bool,
+    assisted: bool,
+    /// Cycle the run was started from, later than 0 for a time attack with a late start.
+    start_cycle: u8,
+    /// Picked up from a checkpoint instead of started fresh.
+    continued: bool,
+    stopwatch: Stopwatch,
+    /// Stopwatch time the current cycle was entered at.
+    split_start: f32,
+    scene_cycle: Option<u32>,
+}
+
+impl FallingRun {
+    // Story spheres are laid out once per pair of cycles, see `sys_scene_change`
+    fn sphere_seed(&self) -> u64 {
+        let layout = match self.mode {
+            RunMode::Story | RunMode::TimeAttack => {
+                (self.cycle_number - self.cycle_number % 2) as u64
+            }
+            RunMode::Endless => self.segment as u64,
+        };
+
+        self.seed.wrapping_add(layout)
+    }
+
+    pub(crate) fn elapsed_secs(&self) -> f32 {
+        self.stopwatch.elapsed_secs()
+    }
+
+    pub(crate) fn current_split(&self) -> Split {
+        Split {
+            cycle: self.cycle_number,
+            time: self.stopwatch.elapsed_secs() - self.split_start,
+        }
+    }
+
+    fn stats(&self) -> GameStats {
+        GameStats {
+            time: self.stopwatch.elapsed_secs() as u32,
+            assisted: self.assisted,
+            mode: self.mode,
+            depth: self.depth as u32,
+            splits: self.splits.clone(),
+            score: self.score.points as u32,
+        }
+    }
+
+    fn death_report(&self, cause: DeathCause) -> DeathReport {
+        DeathReport {
+            cause,
+            mode: self.mode,
+            cycle: self.cycle_number,
+            depth: self.depth as u32,
+            time: self.stopwatch.elapsed_secs() as u32,
+            pickups: self.pickups,
+            top_speed: self.top_speed,
+            history: self.history.clone(),
+        }
+    }
 }
 
 // Physics
 
 // Define your physics layers
 #[derive(PhysicsLayer)]
-enum Layer {
+pub(crate) enum Layer {
     World,
     Player,
     Teleport,
@@ -150,46 +231,153 @@ enum Layer {
 
 // GAMEPLAY VARIABLES
 
-const RADIUS: f32 = 8.5;
+pub(crate) const RADIUS: f32 = 8.5;
+/// Falling faster than this hurts.
+pub(crate) const DAMAGE_SPEED: f32 = 100.0;
+const SHAKE_PER_DAMAGE: f32 = 0.25;
+/// Damage keeps coming in every stats step, it shakes the camera once per interval.
+const DAMAGE_SHAKE_INTERVAL: f32 = 0.4;
+const STATS_STEP: f64 = 0.05;
+const HISTORY_INTERVAL: f32 = 0.5;
+const WALL_SCRAPE_MARGIN: f32 = 0.3;
+const WALL_SPARK_INTERVAL: f32 = 0.06;
+// The second track takes over from cycle 4
+const FALLING_TRACKS: [&str; 2] = ["music/falling-1.mp3", "music/falling-2.mp3"];
+// Endless segments cycle through the story's backdrops
+const ENDLESS_CLEAR_COLORS: [Color; 4] = [
+    Color::rgb(0.0, 0.0, 0.0),
+    Color::rgb(1.0, 1.0, 1.0),
+    Color::rgb(0.0, 0.1, 0.1),
+    Color::rgb(0.8, 0.8, 0.8),
+];
+
+/// Runs that end by dying have changed state by the time anything ordered after this does.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct PlayerDiedLabel;
 
 // Systems
 fn sys_spawn_player(
     mut commands: Commands,
-    audio: Res<Audio>,
     asset_server: Res<AssetServer>,
-    // mut meshes: ResMut<Assets<Mesh>>,
-    // mut materials: ResMut<Assets<StandardMaterial>>,
-    mut stopwatch: ResMut<Stopwatch>,
+    mut music: EventWriter<PlayMusic>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut run_options: ResMut<RunOptions>,
 ) {
-    audio.play_looped(asset_server.load("music/falling-1.mp3"));
+    let mut actor_bundle = new_actor_bundle();
 
-    commands.insert_resource(FallingState { cycle_number: 0 });
+    let checkpoint = match run_options.continue_from_checkpoint {
+        true => Checkpoint::load(),
+        false => None,
+    };
+    run_options.continue_from_checkpoint = false;
+
+    let state = match checkpoint {
+        Some(checkpoint) => {
+            let mut stopwatch = Stopwatch::new();
+            stopwatch.set_elapsed(Duration::from_secs_f32(checkpoint.elapsed));
+
+            actor_bundle.actor.health = checkpoint.health;
+            actor_bundle.actor.velocity = checkpoint.velocity;
+            actor_bundle.velocity.linear.y = checkpoint.velocity;
+            actor_bundle.transform.translation.y = 3000.0;
+
+            FallingRun {
+                cycle_number: checkpoint.cycle_number,
+                segment: checkpoint.cycle_number as u32,
+                seed: checkpoint.seed,
+                restored: true,
+                assisted: true,
+                start_cycle: checkpoint.cycle_number,
+                continued: true,
+                stopwatch,
+                split_start: checkpoint.elapsed,
+                ..default()
+            }
+        }
+        None => {
+            // Only a new story run gives up the story's checkpoint
+            if run_options.mode == RunMode::Story {
+                Checkpoint::clear();
+            }
 
-    let camera_transform = Transform::from_matrix(Mat4::from_rotation_translation(
-        Quat::from_rotation_x(-std::f32::consts::PI / 2.0).normalize(),
-        Vec3::new(0.0, 0.0, 0.0),
-    ));
+            let start_cycle = match run_options.mode {
+                RunMode::TimeAttack => run_options.start_cycle.min(STORY_CYCLES - 1),
+                _ => 0,
+            };
 
-    let actor_bundle = new_actor_bundle();
+            FallingRun {
+                cycle_number: start_cycle,
+                segment: start_cycle as u32,
+                seed: rand::thread_rng().gen(),
+                mode: run_options.mode,
+                // A late start sets its scene up like a restored run and doesn't rank clean
+                restored: start_cycle > 0,
+                assisted: start_cycle > 0,
+                start_cycle,
+                ..default()
+            }
+        }
+    };
+
+    commands.insert_resource(Shaft::new(state.sphere_seed(), SHAFT_FLOORS));
+
+    let track = if state.cycle_number >= 4 { 1 } else { 0 };
+    music.send(PlayMusic::Track(FALLING_TRACKS[track]));
+
+    let camera_transform = Transform::from_translation(actor_bundle.transform.translation)
+        .with_rotation(Quat::from_rotation_x(-std::f32::consts::PI / 2.0));
 
     // Camera
-    commands.spawn_bundle(actor_bundle).with_children(|parent| {
-        parent.spawn_bundle(PerspectiveCameraBundle {
+    commands
+        .spawn_bundle(PerspectiveCameraBundle {
             transform: camera_transform,
             ..Default::default()
-        });
+        })
+        .insert(GameCamera::default())
+        .insert(AudioListener)
+        .insert(StateScoped(AppState::FallingGame));
 
-        parent.spawn_bundle(PlayerBundle::default());
+    let actor_mesh = meshes.add(Mesh::from(shape::UVSphere {
+        radius: 0.5,
+        ..default()
+    }));
+    let actor_material = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.9, 0.9, 0.9),
+        ..default()
     });
 
-    // Stopwatch
-    stopwatch.reset();
+    commands
+        .spawn_bundle(actor_bundle)
+        .insert(CameraTarget)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(PbrBundle {
+                    mesh: actor_mesh,
+                    material: actor_material,
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(CameraTargetModel);
+
+            parent.spawn_bundle(PlayerBundle::default());
+
+            // Embers stream up the shaft around the player
+            parent.spawn_bundle(ParticleEmitterBundle::new(
+                asset_server.load("particles/embers.particles"),
+                Transform::from_xyz(0.0, -20.0, 0.0),
+            ));
+        });
+
+    commands.insert_resource(state);
+    commands.insert_resource(IndoctrinationSettings { enabled: false });
 }
 
 fn sys_spawn_teleport(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut fire_materials: ResMut<Assets<FireMaterial>>,
 ) {
     // sphere light
 
@@ -199,27 +387,27 @@ fn sys_spawn_teleport(
         ..default()
     }));
 
-    let material = materials.add(StandardMaterial {
-        base_color: Color::rgb(1.0, 0.1, 0.1),
-        unlit: true,
+    let material = fire_materials.add(FireMaterial {
+        base_color: Color::rgb_linear(1.0, 0.1, 0.1),
+        flame_height: 0.5,
         ..default()
     });
 
     commands
         .spawn_bundle(TeleportBundle {
-            pbr_bundle: PbrBundle {
+            material_mesh_bundle: MaterialMeshBundle {
                 mesh: mesh.clone(),
                 material: material,
                 transform: Transform::from_xyz(0.0, 0.0, 0.0).with_scale(Vec3::splat(10.0)),
                 ..default()
             },
-            marker: FallingGameComponent,
             teleport: Teleport,
             rigid_body: RigidBody::Static,
             collision_shape: CollisionShape::Sphere { radius: 8.5 },
             collision_layers: CollisionLayers::new(Layer::Teleport, Layer::Player),
         })
         .insert(RigidBody::Static)
+        .insert(StateScoped(AppState::FallingGame))
         .with_children(|children| {
             children.spawn_bundle(PointLightBundle {
                 point_light: PointLight {
@@ -231,77 +419,14 @@ fn sys_spawn_teleport(
                 ..default()
             });
         });
-}
-
-fn sys_spawn_game_spheres(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let mut rng = rand::thread_rng();
-
-    for j in 0..300 {
-        let y = (j as f32) * 10.0;
 
-        if j % 3 != 0 {
-            continue;
-        }
-
-        let cube_type = match rng.gen_range(0..3) {
-            0 => CubeType::Brake,
-            1 => CubeType::Health,
-            2 => CubeType::Speed,
-            _ => CubeType::Environment,
-        };
-
-        let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
-        let radius = rng.gen_range(0.0..(RADIUS - 1.0));
-
-        let color = match cube_type {
-            CubeType::Brake => Color::rgba(0.7, 0.1, 0.1, 0.3),
-            CubeType::Health => Color::rgba(0.2, 0.7, 0.1, 0.3),
-            CubeType::Speed => Color::rgba(0.2, 0.1, 0.7, 0.3),
-            _ => Color::WHITE,
-        };
-
-        let material = materials.add(StandardMaterial {
-            base_color: color,
-            reflectance: 0.7,
-            alpha_mode: AlphaMode::Opaque,
-            perceptual_roughness: 0.08,
-            ..default()
-        });
-
-        let x = f32::sin(angle) * radius;
-        let z = f32::cos(angle) * radius;
-
-        let mesh = meshes.add(Mesh::from(shape::UVSphere {
-            sectors: 128,
-            stacks: 64,
-            ..default()
-        }));
-
-        commands
-            .spawn_bundle(CubeBundle {
-                cube: Cube {
-                    cube_type: cube_type,
-                },
-                pbr_bundle: PbrBundle {
-                    mesh: mesh,
-                    material: material.clone(),
-                    transform: Transform::from_xyz(x, y, z).with_scale(Vec3::splat(1.0)),
-                    ..default()
-                },
-                ..default()
-            })
-            .insert(CollisionShape::Sphere { radius: 1.0 })
-            .insert(
-                CollisionLayers::none()
-                    .with_group(Layer::World)
-                    .with_masks(&[Layer::Player]),
-            )
-            .insert(RigidBody::Sensor);
-    }
+    // Not a child of the teleport, its scale would blow the particles up
+    commands
+        .spawn_bundle(ParticleEmitterBundle::new(
+            asset_server.load("particles/teleport_vortex.particles"),
+            Transform::from_xyz(0.0, 0.0, 0.0),
+        ))
+        .insert(StateScoped(AppState::FallingGame));
 }
 
 fn sys_spawn_environment(
@@ -309,71 +434,21 @@ fn sys_spawn_environment(
     mut meshes: ResMut<Assets<Mesh>>,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut bmaterials: ResMut<Assets<BloodfieldMaterial>>,
 ) {
-    // Spawn Circle of Cubes
-
-    for j in 0..300 {
-        let y = (j as f32) * 10.0;
-
-        let material;
-
-        if j % (2 as u16) == 0 {
-            material = materials.add(Color::rgb(0.8, 0.1, 0.1).into());
-        } else {
-            material = materials.add(Color::rgb(0.8, 0.2, 0.1).into());
-        }
-
-        commands
-            .spawn_bundle(PbrBundle { ..default() })
-            .with_children(|parent| {
-                for i in 0..11 {
-                    let angle = std::f32::consts::PI * 2.0 / 11.0 * (i as f32);
-
-                    let x = f32::sin(angle) * RADIUS;
-                    let z = f32::cos(angle) * RADIUS;
-
-                    parent.spawn_bundle(CubeBundle {
-                        pbr_bundle: PbrBundle {
-                            mesh: meshes.add(Mesh::from(shape::Cube { size: 0.8 })),
-                            material: material.clone(),
-                            transform: Transform::from_xyz(x, y, z),
-                            ..default()
-                        },
-                        ..default()
-                    });
-
-                    let rotation = Quat::from_rotation_y(std::f32::consts::PI / 4.0);
-
-                    let mut transform = Transform::from_xyz(x, y, z);
-                    transform.rotate(rotation);
-
-                    parent.spawn_bundle(CubeBundle {
-                        pbr_bundle: PbrBundle {
-                            mesh: meshes.add(Mesh::from(shape::Cube { size: 0.8 })),
-                            material: material.clone(),
-                            transform: transform,
-                            ..default()
-                        },
-                        ..default()
-                    });
-                }
-
-                // Spawn light source
-                parent.spawn_bundle(PointLightBundle {
-                    point_light: PointLight {
-                        intensity: 2000.0,
-                        shadows_enabled: false,
-                        ..Default::default()
-                    },
-                    transform: Transform::from_xyz(0.0, y, 0.0),
-                    ..Default::default()
-                });
-            })
-            .insert(Floor {
-                direction: (j % (2 as u16)) as u8,
-            })
-            .insert(FallingGameComponent);
-    }
+    // Far backdrop under the teleport
+    commands
+        .spawn_bundle(MaterialMeshBundle {
+            mesh: meshes.add(Mesh::from(shape::Plane { size: 1. })),
+            material: bmaterials.add(BloodfieldMaterial::from_preset(
+                asset_server.load("bloodfield/shaft.bloodfield"),
+                rand::thread_rng().gen::<i16>() as f32,
+            )),
+            transform: Transform::from_xyz(0.0, -300.0, 0.0)
+                .with_scale(Vec3::new(1500.0, 1.0, 1500.0)),
+            ..default()
+        })
+        .insert(StateScoped(AppState::FallingGame));
 
     // spawn chain
 
@@ -390,304 +465,154 @@ fn sys_spawn_environment(
             material: materials.add(Color::WHITE.into()),
             ..default()
         })
-        .insert(Chain);
-}
-
-fn sys_animate_environment(
-    time: Res<Time>,
-    mut query_cube: Query<(&mut Transform, &Cube), With<Cube>>,
-    mut query_floor: Query<(&mut Transform, &Floor), (With<Floor>, Without<Cube>)>,
-    mut lines: ResMut<DebugLines>,
-    state: Res<FallingState>,
-) {
-    if state.cycle_number == 0 || state.cycle_number == 2 || state.cycle_number == 4 {
-        for (mut transform, cube) in query_cube.iter_mut() {
-            if cube.cube_type != CubeType::Environment {
-                continue;
-            }
-            transform.rotation *= Quat::from_rotation_x(1.0 * time.delta_seconds());
-            transform.rotation *= Quat::from_rotation_y(0.7 * time.delta_seconds());
-        }
-
-        for (mut transform, floor) in query_floor.iter_mut() {
-            let mut dir = match floor.direction {
-                0 => -1.0,
-                _ => 1.0,
-            };
-
-            if state.cycle_number == 4 {
-                dir = -1.0;
-            }
-
-            transform.rotation *= Quat::from_rotation_y(dir * 1.0 * time.delta_seconds());
-        }
-    }
-
-    if state.cycle_number == 2 || state.cycle_number == 6 {
-        let transforms: Vec<&Transform> = query_cube.iter().map(|(t, _)| t).collect();
-        let floor_transforms: Vec<(&Transform, &Floor)> = query_floor.iter().collect();
-
-        for j in 0..300 {
-            let y = 10.0 * (j as f32);
-            let rotation_quat = floor_transforms[j].0.rotation;
-
-            // Spawn Circle of Cubes
-            for i in 0..11 {
-                let index_end;
-                if i + 4 < 11 {
-                    index_end = i + 4;
-                } else {
-                    index_end = i + 4 - 11;
-                }
-
-                let mut start_line =
-                    rotation_quat.mul_vec3(transforms[j * 11 + i * 2].translation) * 2.0;
-                start_line.y = y;
-
-                let mut end_line =
-                    rotation_quat.mul_vec3(transforms[j * 11 + index_end * 2].translation) * 2.0;
-                end_line.y = y;
-
-                if state.cycle_number == 2 || state.cycle_number == 6 {
-                    lines.line_colored(start_line, end_line, 0.1, Color::rgba(0.1, 0.1, 0.1, 0.8));
-                }
-            }
-        }
-    }
+        .insert(Chain)
+        .insert(StateScoped(AppState::FallingGame));
 }
 
 fn sys_adjust_actor_stats(
-    mut commands: Commands,
     mut query_actor: Query<(&Velocity, &mut Actor)>,
-    mut app_state: ResMut<State<AppState>>,
-    audio: Res<Audio>,
-    asset_server: Res<AssetServer>,
+    mut music_state: ResMut<MusicState>,
+    mut post_process: ResMut<PostProcessIntensity>,
+    mut fire_intensity: ResMut<FireIntensity>,
+    mut sounds: EventWriter<PlaySound>,
+    mut damage_taken: EventWriter<DamageTaken>,
+    mut top_speed: EventWriter<TopSpeedReached>,
+    mut damage_zone: EventWriter<DamageZoneLeft>,
+    mut died: EventWriter<PlayerDied>,
+    state: Option<ResMut<FallingRun>>,
+    indoctrination: Option<ResMut<IndoctrinationSettings>>,
 ) {
+    // Runs on a fixed timestep outside the state machine, so there may be no run
+    let (mut state, mut indoctrination) = match (state, indoctrination) {
+        (Some(state), Some(indoctrination)) => (state, indoctrination),
+        _ => return,
+    };
+
     for (v, mut a) in query_actor.iter_mut() {
         let abs_speed = f32::abs(v.linear.y);
 
-        if abs_speed <= 100.0 {
-            audio.set_playback_rate(abs_speed / 100.);
-        } else {
-            audio.set_playback_rate(1.0);
+        let playback_rate = f32::min(abs_speed / 100., 1.0);
+        if (music_state.playback_rate - playback_rate).abs() > 0.01 {
+            music_state.playback_rate = playback_rate;
         }
 
+        post_process.speed = abs_speed;
+        post_process.health = a.health;
+        fire_intensity.speed = abs_speed;
+
         a.velocity = (a.velocity + v.linear.y) / 2.0;
 
-        if f32::abs(v.linear.y) > 100.0 {
-            a.health -= abs_speed / 100.0 / 3.0;
+        if abs_speed > state.top_speed {
+            state.top_speed = abs_speed;
+            top_speed.send(TopSpeedReached { speed: abs_speed });
+        }
+
+        if abs_speed > DAMAGE_SPEED {
+            a.damage_zone_time += STATS_STEP as f32;
+
+            let damage = abs_speed / DAMAGE_SPEED / 3.0;
+            a.health -= damage;
+            damage_taken.send(DamageTaken { amount: damage });
 
             if a.scream_last_play.is_none()
                 || (a.scream_last_play.is_some()
                     && a.scream_last_play.unwrap().elapsed().as_secs() > 2)
             {
                 if a.health < 100.0 {
-                    commands.insert_resource(IndoctrinationSettings { enabled: true });
-                    audio.play(asset_server.load("music/aaa-1.mp3"));
+                    indoctrination.enabled = true;
+                    sounds.send(PlaySound::voice("music/aaa-1.mp3"));
                     a.scream_last_play = Some(std::time::Instant::now());
                 } else {
-                    commands.insert_resource(IndoctrinationSettings { enabled: false });
+                    indoctrination.enabled = false;
                 }
             }
         } else {
-            commands.insert_resource(IndoctrinationSettings { enabled: false });
-        }
+            indoctrination.enabled = false;
 
-        if a.health <= 0.0 {
-            if *app_state.current() != AppState::GameOver {
-                app_state.set(AppState::GameOver).unwrap();
+            if a.damage_zone_time > 0.0 && a.health > 0.0 {
+                damage_zone.send(DamageZoneLeft {
+                    seconds: a.damage_zone_time,
+                });
             }
+            a.damage_zone_time = 0.0;
         }
-    }
-}
-
-fn sys_clear_entities(
-    mut commands: Commands,
-    mut game_objects: Query<Entity, With<FallingGameComponent>>,
-    mut interface: Query<Entity, With<Interface>>,
-    mut actors: Query<Entity, With<Actor>>,
-) {
-    for e in game_objects.iter_mut() {
-        commands.entity(e).despawn_recursive();
-    }
 
-    for e in interface.iter_mut() {
-        commands.entity(e).despawn_recursive();
-    }
-
-    for e in actors.iter_mut() {
-        commands.entity(e).despawn_recursive();
+        if a.health <= 0.0 && !state.dead {
+            state.dead = true;
+            died.send(PlayerDied {
+                cause: DeathCause::TerminalVelocity,
+            });
+        }
     }
-
-    commands.insert_resource(IndoctrinationSettings { enabled: false });
-
-    // audio.stop();
 }
 
-// HUD
-
-fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands
-        .spawn_bundle(UiCameraBundle::default())
-        .insert(FallingGameComponent);
-
-    let font = asset_server.load("fonts/ARCADECLASSIC.ttf");
-    let velocity_text = Text::with_section(
-        "",
-        TextStyle {
-            font_size: 35.0,
-            font: font.clone(),
-            color: Color::WHITE,
-        },
-        TextAlignment {
-            horizontal: HorizontalAlign::Center,
-            ..Default::default()
-        },
-    );
-
-    let health_text = Text::with_section(
-        "",
-        TextStyle {
-            font_size: 35.0,
-            font: font.clone(),
-            color: Color::GREEN,
-        },
-        TextAlignment {
-            horizontal: HorizontalAlign::Center,
-            ..Default::default()
-        },
-    );
-
-    let stopwatch_text = Text::with_section(
-        "",
-        TextStyle {
-            font_size: 35.0,
-            font: font.clone(),
-            color: Color::BLUE,
-        },
-        TextAlignment {
-            horizontal: HorizontalAlign::Center,
-            ..Default::default()
-        },
-    );
-
-    commands
-        .spawn_bundle(NodeBundle {
-            style: Style {
-                size: Size {
-                    width: Val::Percent(100.0),
-                    height: Val::Percent(100.0),
-                },
-                justify_content: JustifyContent::Center,
-                ..Default::default()
-            },
-            visibility: Visibility { is_visible: false },
-            ..Default::default()
-        })
-        .insert(Interface)
-        .with_children(|parent| {
-            parent.spawn_bundle(ImageBundle {
-                style: Style {
-                    size: Size {
-                        width: Val::Auto,
-                        height: Val::Px(300.0),
-                    },
-                    position: Rect {
-                        top: Val::Px(167.0),
-                        ..Default::default()
-                    },
-                    position_type: PositionType::Absolute,
-                    align_self: AlignSelf::Center,
-                    ..Default::default()
-                },
-                image: asset_server.load("images/speedometer.png").into(),
-                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-                ..Default::default()
-            });
-
-            parent
-                .spawn_bundle(TextBundle {
-                    text: velocity_text.clone(),
-                    style: Style {
-                        position_type: PositionType::Absolute,
-                        justify_content: JustifyContent::Center,
-                        position: Rect {
-                            top: Val::Px(450.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(VelocityText);
-
-            parent
-                .spawn_bundle(TextBundle {
-                    text: health_text.clone(),
-                    style: Style {
-                        position_type: PositionType::Absolute,
-                        justify_content: JustifyContent::Center,
-                        position: Rect {
-                            top: Val::Px(480.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(HealthText);
+fn sys_shake_on_damage(
+    time: Res<Time>,
+    mut state: ResMut<FallingRun>,
+    mut damage_taken: EventReader<DamageTaken>,
+    mut camera_shake: EventWriter<CameraShake>,
+) {
+    state.shake_cooldown -= time.delta_seconds();
+    state.shake_damage += damage_taken.iter().map(|event| event.amount).sum::<f32>();
 
-            parent
-                .spawn_bundle(TextBundle {
-                    text: stopwatch_text.clone(),
-                    style: Style {
-                        position_type: PositionType::Absolute,
-                        justify_content: JustifyContent::Center,
-                        position: Rect {
-                            top: Val::Px(510.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(StopwatchText);
+    // Kicks in the damage zone, instead of a shake that never settles
+    if state.shake_damage > 0.0 && state.shake_cooldown <= 0.0 {
+        camera_shake.send(CameraShake {
+            trauma: state.shake_damage * SHAKE_PER_DAMAGE,
         });
+        state.shake_damage = 0.0;
+        state.shake_cooldown = DAMAGE_SHAKE_INTERVAL;
+    }
 }
 
-pub(crate) fn sys_update_hud(
-    player_query: Query<(&Actor, &Velocity)>,
-    mut set: ParamSet<(
-        Query<&mut Text, With<VelocityText>>,
-        Query<&mut Text, With<HealthText>>,
-        Query<&mut Text, With<StopwatchText>>,
-    )>,
-    mut stopwatch: ResMut<Stopwatch>,
-    time: Res<Time>,
+fn sys_player_died(
+    mut commands: Commands,
+    state: Res<FallingRun>,
+    mut app_state: ResMut<State<AppState>>,
+    mut died: EventReader<PlayerDied>,
 ) {
-    let player = player_query.iter().last().unwrap();
-    let velocity = player.1;
-    let actor = player.0;
+    let cause = match died.iter().next() {
+        Some(event) => event.cause,
+        None => return,
+    };
 
-    stopwatch.tick(Duration::from_secs_f32(time.delta_seconds()));
+    // Counted when it happens, endless runs never see the game over screen
+    let mut death_stats = DeathStats::load();
+    death_stats.record(cause);
+    death_stats.save();
 
-    for mut text in set.p0().iter_mut() {
-        let str = format!("speed   {}", -(velocity.linear.y as i32)).to_string();
-        text.sections[0].value = str;
+    match state.mode {
+        // Dying is the only way an endless run ends, it goes straight to the results
+        RunMode::Endless => {
+            commands.insert_resource(state.stats());
+            app_state.set(AppState::GameEnd).unwrap();
+        }
+        _ => {
+            commands.insert_resource(state.death_report(cause));
+            app_state.set(AppState::GameOver).unwrap();
+        }
     }
+}
 
-    for mut text in set.p1().iter_mut() {
-        let str = format!("health   {}", (actor.health as i32)).to_string();
-        text.sections[0].value = str;
-    }
+fn sys_end_run(
+    mut commands: Commands,
+    mut music_state: ResMut<MusicState>,
+    mut post_process: ResMut<PostProcessIntensity>,
+    mut fire_intensity: ResMut<FireIntensity>,
+    mut camera_mode: ResMut<CameraMode>,
+) {
+    commands.remove_resource::<FallingRun>();
+    commands.remove_resource::<IndoctrinationSettings>();
+
+    // The next run starts out like the first one after launch
+    music_state.playback_rate = 1.0;
+    *post_process = PostProcessIntensity::default();
+    fire_intensity.speed = 0.0;
+    *camera_mode = CameraMode::default();
+}
 
-    for mut text in set.p2().iter_mut() {
-        let str = format!(
-            "elapsed {} sec",
-            (stopwatch.elapsed_secs() as i32).to_string()
-        );
-        text.sections[0].value = str;
-    }
+fn sys_tick_stopwatch(mut state: ResMut<FallingRun>, time: Res<Time>) {
+    state.stopwatch.tick(time.delta());
 }
 
 // Control
@@ -706,8 +631,7 @@ bitflags! {
 
 pub(crate) fn sys_keyboard_control(
     keys: Res<Input<KeyCode>>,
-    player_movement_q: Query<(&mut heron::prelude::Velocity, &mut Transform), With<Actor>>,
-    collision_events: EventReader<CollisionEvent>,
+    player_movement_q: Query<(&mut heron::prelude::Velocity, &mut Transform, &mut Actor)>,
 ) {
     let mut player_action = PlayerActionFlags::IDLE;
 
@@ -729,25 +653,16 @@ pub(crate) fn sys_keyboard_control(
         }
     }
 
-    control_player(player_action, player_movement_q, collision_events);
+    control_player(player_action, player_movement_q);
 }
 
 pub(crate) fn control_player(
     player_action: PlayerActionFlags,
-    mut player_movement_q: Query<(&mut heron::prelude::Velocity, &mut Transform), With<Actor>>,
-    mut collision_events: EventReader<CollisionEvent>,
+    mut player_movement_q: Query<(&mut heron::prelude::Velocity, &mut Transform, &mut Actor)>,
 ) {
-    fn is_player(layers: CollisionLayers) -> bool {
-        layers.contains_group(Layer::Player) && !layers.contains_group(Layer::World)
-    }
-
-    fn is_world(layers: CollisionLayers) -> bool {
-        !layers.contains_group(Layer::Player) && layers.contains_group(Layer::World)
-    }
-
     const SPEED: f32 = 0.3;
 
-    for (mut velocity, mut transform) in player_movement_q.iter_mut() {
+    for (mut velocity, mut transform, mut actor) in player_movement_q.iter_mut() {
         if player_action.contains(PlayerActionFlags::UP) {
             let delta = transform.translation + Vec3::new(0.0, 0.0, -SPEED);
             let radius = (delta.x.powf(2.0) + delta.z.powf(2.0)).sqrt();
@@ -777,117 +692,81 @@ pub(crate) fn control_player(
             }
         }
 
-        if player_action.contains(PlayerActionFlags::BRAKE) {
-            if velocity.linear.y < 0.0 {
-                velocity.linear.y += 1.0;
-            }
+        actor.braking = player_action.contains(PlayerActionFlags::BRAKE) && velocity.linear.y < 0.0;
+        if actor.braking {
+            actor.braked = true;
+            velocity.linear.y += 1.0;
         }
-
-        collision_events
-            .iter()
-            .filter_map(|event| {
-                let (entity_1, entity_2) = event.rigid_body_entities();
-                let (layers_1, layers_2) = event.collision_layers();
-
-                if is_player(layers_1) && is_world(layers_2) {
-                    Some(entity_2)
-                } else if is_player(layers_2) && is_world(layers_1) {
-                    Some(entity_1)
-                } else {
-                    None
-                }
-            })
-            .for_each(|_| {
-                *velocity = Velocity::from_linear(Vec3::X * 0.0);
-            });
     }
 }
 
-fn sys_check_teleport_collision(
-    mut collision_events: EventReader<CollisionEvent>,
+fn sys_enter_teleport(
+    mut teleports: EventReader<TeleportEntered>,
     mut query_actor: Query<(&mut Transform, &mut Velocity, &Actor)>,
-    mut state: ResMut<FallingState>,
+    mut state: ResMut<FallingRun>,
+    mut pulse: EventWriter<DesaturationPulse>,
+    mut run_finished: EventWriter<RunFinished>,
 ) {
-    fn is_player(layers: CollisionLayers) -> bool {
-        layers.contains_group(Layer::Player) && !layers.contains_group(Layer::Teleport)
-    }
-
-    fn is_teleport(layers: CollisionLayers) -> bool {
-        !layers.contains_group(Layer::Player) && layers.contains_group(Layer::Teleport)
-    }
+    for event in teleports.iter() {
+        let split = state.current_split();
+        state.splits.push(split);
+        state.split_start += split.time;
+
+        state.cycle_number = event.cycle;
+        state.segment += 1;
+        state.restored = false;
+        pulse.send(DesaturationPulse);
+
+        for (mut t, mut v, a) in query_actor.iter_mut() {
+            // Endless runs keep their speed, the shaft just goes on
+            if state.mode != RunMode::Endless && state.cycle_number <= 6 {
+                v.linear.y = 0.0;
+            }
+            t.translation.y = 3000.0;
 
-    let events = collision_events
-        .iter()
-        .filter_map(|event| {
-            let (entity_1, entity_2) = event.rigid_body_entities();
-            let (layers_1, layers_2) = event.collision_layers();
-
-            if is_player(layers_1) && is_teleport(layers_2) {
-                Some(entity_2)
-            } else if is_player(layers_2) && is_teleport(layers_1) {
-                Some(entity_1)
-            } else {
-                None
+            if state.mode == RunMode::Story && state.cycle_number < STORY_CYCLES {
+                Checkpoint {
+                    cycle_number: state.cycle_number,
+                    seed: state.seed,
+                    health: a.health,
+                    velocity: v.linear.y,
+                    elapsed: state.stopwatch.elapsed_secs(),
+                }
+                .save();
             }
-        })
-        .count();
 
-    if events > 0 {
-        for (mut t, mut v, _) in query_actor.iter_mut() {
-            if state.cycle_number < 6 {
-                v.linear.y = 0.0;
+            if state.mode != RunMode::Endless && state.cycle_number == STORY_CYCLES {
+                run_finished.send(RunFinished {
+                    health: a.health,
+                    braked: a.braked,
+                    start_cycle: state.start_cycle,
+                    continued: state.continued,
+                });
             }
-            t.translation.y = 3000.0;
         }
-
-        state.cycle_number += 1;
     }
 }
 
-fn sys_check_game_cube_collision(
+fn sys_collect_pickups(
     mut commands: Commands,
-    mut query_actor: Query<(&mut Transform, &mut Velocity, &mut Actor)>,
-    query_cubes: Query<(Entity, &Transform, &Cube), Without<Actor>>,
-    audio: Res<Audio>,
-    asset_server: Res<AssetServer>,
-    mut collision_events: EventReader<CollisionEvent>,
+    mut pickups: EventReader<PickupCollected>,
+    mut query_actor: Query<(&mut Velocity, &mut Actor)>,
+    mut shaft: ResMut<Shaft>,
+    mut state: ResMut<FallingRun>,
 ) {
-    fn is_player(layers: CollisionLayers) -> bool {
-        layers.contains_group(Layer::Player) && !layers.contains_group(Layer::World)
-    }
+    for pickup in pickups.iter() {
+        commands.entity(pickup.entity).despawn_recursive();
+        shaft.collect(pickup.entity);
 
-    fn is_world(layers: CollisionLayers) -> bool {
-        !layers.contains_group(Layer::Player) && layers.contains_group(Layer::World)
-    }
-
-    let collision = collision_events
-        .iter()
-        .filter_map(|event| {
-            let (entity_1, entity_2) = event.rigid_body_entities();
-            let (layers_1, layers_2) = event.collision_layers();
-
-            if is_player(layers_1) && is_world(layers_2) {
-                Some(entity_2)
-            } else if is_player(layers_2) && is_world(layers_1) {
-                Some(entity_1)
-            } else {
-                None
-            }
-        })
-        .last();
-
-    if collision.is_some() {
-        let cube = query_cubes
-            .iter()
-            .filter(|(e, _, _)| *e == collision.unwrap())
-            .map(|(_, _, c)| c)
-            .last()
-            .unwrap();
+        if pickup.kind != CubeType::Environment {
+            state.pickups += 1;
+        }
 
-        for (_, mut v, mut a) in query_actor.iter_mut() {
-            commands.entity(collision.unwrap()).despawn_recursive();
+        for (mut v, mut a) in query_actor.iter_mut() {
+            // Scored at the speed it was taken at, so a brake pickup breaks the combo
+            state.score.pickup(-v.linear.y);
 
-            match cube.cube_type {
+            match pickup.kind {
                 CubeType::Brake => {
                     a.velocity += 40.0;
                 }
@@ -901,95 +780,168 @@ fn sys_check_game_cube_collision(
             }
 
             v.linear.y = a.velocity;
-            audio.play(asset_server.load("music/box-hit.mp3"));
         }
     }
 }
 
+fn sys_pickup_feedback(
+    mut pickups: EventReader<PickupCollected>,
+    mut sounds: EventWriter<PlaySound>,
+    mut particles: EventWriter<SpawnParticles>,
+) {
+    for pickup in pickups.iter() {
+        sounds.send(PlaySound::sfx("music/box-hit.mp3").at(pickup.position));
+        particles.send(
+            SpawnParticles::burst("particles/pickup.particles", pickup.position)
+                .with_color(pickup.kind.color()),
+        );
+    }
+}
+
+fn sys_record_history(
+    time: Res<Time>,
+    mut state: ResMut<FallingRun>,
+    query_actor: Query<(&Velocity, &Actor)>,
+) {
+    state.since_sample += time.delta_seconds();
+    if state.since_sample < HISTORY_INTERVAL {
+        return;
+    }
+    state.since_sample = 0.0;
+
+    for (velocity, actor) in query_actor.iter() {
+        state.history.push(RunSample {
+            speed: -velocity.linear.y,
+            health: actor.health,
+        });
+    }
+}
+
+fn sys_track_depth(mut state: ResMut<FallingRun>, query_actor: Query<&Transform, With<Actor>>) {
+    for transform in query_actor.iter() {
+        let fallen = (SEGMENT_DEPTH - transform.translation.y).max(0.0);
+        let depth = state.segment as f32 * SEGMENT_DEPTH + fallen;
+        state.depth = state.depth.max(depth);
+    }
+}
+
+fn sys_wall_sparks(
+    time: Res<Time>,
+    mut state: ResMut<FallingRun>,
+    query_actor: Query<&Transform, With<Actor>>,
+    mut particles: EventWriter<SpawnParticles>,
+) {
+    state.spark_cooldown -= time.delta_seconds();
+    if state.spark_cooldown > 0.0 {
+        return;
+    }
+
+    for transform in query_actor.iter() {
+        let horizontal = Vec3::new(transform.translation.x, 0.0, transform.translation.z);
+
+        // The controls keep the player one unit off the wall, scraping is riding that limit
+        if horizontal.length() < RADIUS - 1.0 - WALL_SCRAPE_MARGIN {
+            continue;
+        }
+
+        let contact =
+            horizontal.normalize_or_zero() * RADIUS + Vec3::new(0.0, transform.translation.y, 0.0);
+        particles.send(SpawnParticles::burst(
+            "particles/wall_sparks.particles",
+            contact,
+        ));
+        state.spark_cooldown = WALL_SPARK_INTERVAL;
+    }
+}
+
 fn sys_scene_change(
     mut commands: Commands,
-    state: Res<FallingState>,
+    mut state: ResMut<FallingRun>,
+    mut shaft: ResMut<Shaft>,
     mut app_state: ResMut<State<AppState>>,
-    mut query_cube: Query<(Entity, &mut Visibility, &Cube)>,
     mut query_chain: Query<(Entity, &Chain)>,
-    audio: Res<Audio>,
-    asset_server: Res<AssetServer>,
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<StandardMaterial>>,
-    stopwatch: Res<Stopwatch>,
+    mut music: EventWriter<PlayMusic>,
 ) {
-    if !state.is_changed() {
+    // A run restored mid-pair still needs the setup of the cycle that started the pair
+    let cycle_number = match state.restored {
+        true => state.cycle_number - state.cycle_number % 2,
+        false => state.cycle_number,
+    };
+    // Endless segments go on past what a cycle number can count
+    let segment = match state.mode {
+        RunMode::Endless => state.segment,
+        _ => cycle_number as u32,
+    };
+
+    // The stopwatch ticks every frame, so change detection can't tell us about new cycles
+    if state.scene_cycle == Some(segment) {
         return;
     }
+    state.scene_cycle = Some(segment);
 
-    let mut despawn_game_cubes = || {
-        for (e, _, c) in query_cube.iter_mut() {
-            if c.cube_type != CubeType::Environment {
-                commands.entity(e).despawn_recursive();
+    if state.mode == RunMode::Endless {
+        Difficulty::at_depth(segment as f32 * SEGMENT_DEPTH).apply(&mut shaft);
+        for entity in shaft.relayout(state.sphere_seed()) {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        if segment > 0 {
+            for (e, _) in query_chain.iter_mut() {
+                commands.entity(e).despawn();
             }
         }
 
+        let clear_color = ENDLESS_CLEAR_COLORS[segment as usize % ENDLESS_CLEAR_COLORS.len()];
+        commands.insert_resource(ClearColor(clear_color));
+        if segment == 4 {
+            music.send(PlayMusic::Track(FALLING_TRACKS[1]));
+        }
+        return;
+    }
+
+    let mut relayout_shaft = || {
+        for entity in shaft.relayout(state.sphere_seed()) {
+            commands.entity(entity).despawn_recursive();
+        }
+
         for (e, _) in query_chain.iter_mut() {
             commands.entity(e).despawn();
         }
     };
 
-    match state.cycle_number {
+    match cycle_number {
         0 => {
             commands.insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)));
         }
         2 => {
-            despawn_game_cubes();
+            relayout_shaft();
 
             commands.insert_resource(ClearColor(Color::rgb(1.0, 1.0, 1.0)));
-            sys_spawn_game_spheres(commands, meshes, materials);
-
-            for (_, mut v, _) in query_cube.iter_mut() {
-                v.is_visible = false;
-            }
+            shaft.ring_pattern = RingPattern::Hidden;
         }
         4 => {
-            despawn_game_cubes();
+            relayout_shaft();
             commands.insert_resource(ClearColor(Color::rgb(0.0, 0.1, 0.1)));
-            sys_spawn_game_spheres(commands, meshes, materials);
-            audio.stop();
-            audio.play_looped(asset_server.load("music/falling-2.mp3"));
-
-            let mut counter = 0;
-            for (_, mut v, _) in query_cube.iter_mut() {
-                v.is_visible = false;
+            music.send(PlayMusic::Track(FALLING_TRACKS[1]));
 
-                if counter % 5 == 0 {
-                    v.is_visible = true;
-                } else {
-                    v.is_visible = false;
-                }
-
-                counter += 1;
-            }
+            shaft.ring_pattern = RingPattern::EveryNth(5);
         }
         6 => {
-            despawn_game_cubes();
+            relayout_shaft();
             commands.insert_resource(ClearColor(Color::rgb(0.8, 0.8, 0.8)));
-            sys_spawn_game_spheres(commands, meshes, materials);
-
-            let mut counter = 0;
-            for (_, mut v, _) in query_cube.iter_mut() {
-                v.is_visible = false;
 
-                if counter % 7 == 0 {
-                    v.is_visible = true;
-                } else {
-                    v.is_visible = false;
-                }
+            shaft.ring_pattern = RingPattern::EveryNth(7);
+        }
+        STORY_CYCLES => {
+            // Dying on the way through the last teleport ends the run as a death
+            if state.dead {
+                return;
+            }
 
-                counter += 1;
+            if state.mode == RunMode::Story {
+                Checkpoint::clear();
             }
-        }
-        8 => {
-            commands.insert_resource(GameStats {
-                time: stopwatch.elapsed_secs() as u32,
-            });
+            commands.insert_resource(state.stats());
             app_state.set(AppState::GameEnd).unwrap();
         }
         _ => {}
@@ -999,15 +951,17 @@ fn sys_scene_change(
 // Mouse Control
 
 fn sys_mouse_cursor_grab(mut windows: ResMut<Windows>) {
-    let window = windows.get_primary_mut().unwrap();
-    window.set_cursor_lock_mode(true);
-    window.set_cursor_visibility(false);
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_cursor_lock_mode(true);
+        window.set_cursor_visibility(false);
+    }
 }
 
 fn sys_mouse_cursor_ungrab(mut windows: ResMut<Windows>) {
-    let window = windows.get_primary_mut().unwrap();
-    window.set_cursor_lock_mode(false);
-    window.set_cursor_visibility(true);
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_cursor_lock_mode(false);
+        window.set_cursor_visibility(true);
+    }
 }
 
 // Mouse Input
@@ -1086,38 +1040,204 @@ impl Plugin for FallingMinigamePlugin {
         app.add_plugin(PhysicsPlugin::default())
             .add_plugin(EZInputPlugin::<EnumeratedBinding>::default())
             .add_plugin(IndoctrinationPlugin)
+            .add_plugin(CameraModePlugin)
+            .add_plugin(PostProcessPlugin)
+            .add_plugin(FirePlugin)
+            .add_plugin(ShaftPlugin)
+            .add_plugin(TimeAttackPlugin)
+            .add_plugin(ScorePlugin)
+            .add_plugin(GameplayEventsPlugin)
+            .add_plugin(HudPlugin)
             .insert_resource(Gravity::from(Vec3::new(0.0, -9.81, 0.0)))
-            .insert_resource(FallingState { cycle_number: 0 })
-            .insert_resource(IndoctrinationSettings { enabled: false })
-            .insert_resource(Stopwatch::new())
+            .init_resource::<RunOptions>()
             .add_system_set(
                 SystemSet::on_enter(AppState::FallingGame)
-                    .with_system(sys_spawn_game_spheres)
                     .with_system(sys_spawn_player)
-                    .with_system(sys_draw_hud)
                     .with_system(sys_spawn_environment)
                     .with_system(sys_spawn_teleport)
                     .with_system(sys_mouse_cursor_grab),
             )
             .add_system_set(
                 SystemSet::on_update(AppState::FallingGame)
-                    .with_system(sys_animate_environment)
-                    .with_system(sys_update_hud)
+                    .with_system(sys_tick_stopwatch)
                     .with_system(sys_keyboard_control)
-                    .with_system(sys_check_teleport_collision)
-                    .with_system(sys_scene_change)
-                    .with_system(sys_check_game_cube_collision)
+                    .with_system(sys_enter_teleport.after(GameplayEventsLabel))
+                    .with_system(sys_scene_change.after(PlayerDiedLabel))
+                    .with_system(sys_collect_pickups.after(GameplayEventsLabel))
+                    .with_system(sys_pickup_feedback.after(GameplayEventsLabel))
+                    .with_system(sys_shake_on_damage)
+                    .with_system(sys_player_died.label(PlayerDiedLabel))
+                    .with_system(sys_track_depth)
+                    .with_system(sys_record_history)
+                    .with_system(sys_wall_sparks)
                     .with_system(sys_mouse_control),
             )
             .add_system_set(
                 SystemSet::new()
-                    .with_run_criteria(FixedTimestep::step(0.05))
+                    .with_run_criteria(FixedTimestep::step(STATS_STEP))
                     .with_system(sys_adjust_actor_stats),
             )
             .add_system_set(
                 SystemSet::on_exit(AppState::FallingGame)
-                    .with_system(sys_clear_entities)
+                    .with_system(sys_end_run)
                     .with_system(sys_mouse_cursor_ungrab),
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::core::{DefaultTaskPoolOptions, FixedTimesteps};
+    use bevy::ecs::event::Events;
+    use bevy_prototype_debug_lines::DebugLines;
+
+    use super::*;
+    use crate::settings::Settings;
+
+    // Time is left standing, so both launches step through the same frames
+    fn new_app() -> App {
+        let mut app = App::new();
+        DefaultTaskPoolOptions::default().create_default_pools(&mut app.world);
+        app.init_resource::<Time>()
+            .init_resource::<FixedTimesteps>()
+            .add_plugin(bevy::transform::TransformPlugin)
+            .add_plugin(bevy::hierarchy::HierarchyPlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_plugin(bevy::input::InputPlugin)
+            .add_plugin(bevy::window::WindowPlugin::default())
+            .add_asset::<Mesh>()
+            .add_asset::<Image>()
+            .add_asset::<Shader>()
+            .add_asset::<StandardMaterial>()
+            .add_asset::<BloodfieldMaterial>()
+            .add_asset::<FireMaterial>()
+            .add_event::<PlayMusic>()
+            .add_event::<PlaySound>()
+            .add_event::<SpawnParticles>()
+            .init_resource::<DebugLines>()
+            .init_resource::<MusicState>()
+            .init_resource::<Settings>()
+            .add_state(AppState::MainMenu)
+            .add_plugin(StateScopedPlugin)
+            .add_plugin(FallingMinigamePlugin);
+        app.update();
+        app
+    }
+
+    fn switch_state(app: &mut App, state: AppState) {
+        app.world
+            .resource_mut::<State<AppState>>()
+            .set(state)
+            .unwrap();
+        app.update();
+    }
+
+    /// Everything a run starts from, with the random seed taken out.
+    fn run_snapshot(app: &mut App) -> String {
+        let world = &mut app.world;
+        world.resource_mut::<FallingRun>().seed = 0;
+
+        let mut cameras = world.query::<(&GameCamera, &Transform)>();
+        let cameras: Vec<_> = cameras.iter(world).collect();
+        let music_events = world.resource::<Events<PlayMusic>>();
+        let mut music_reader = music_events.get_reader();
+        let music: Vec<_> = music_reader.iter(music_events).collect();
+
+        format!(
+            "{:#?}\n{:?}\n{:?}\n{:?} {:?}\n{:?}\n{:?} {:?}\n{:?}\nentities: {}",
+            world.resource::<FallingRun>(),
+            world.contains_resource::<GameStats>(),
+            world.resource::<FallingRun>().score,
+            world.resource::<CameraMode>(),
+            cameras,
+            world.resource::<MusicState>(),
+            music,
+            world.resource::<PostProcessIntensity>(),
+            world.resource::<FireIntensity>(),
+            world.entities().len(),
+        )
+    }
+
+    /// Leaves behind everything a run changes on the way down.
+    fn play_run(app: &mut App) {
+        let world = &mut app.world;
+        *world.resource_mut::<CameraMode>() = CameraMode::Side;
+        world.resource_mut::<MusicState>().playback_rate = 0.4;
+        world.resource_mut::<PostProcessIntensity>().speed = 130.0;
+        world.resource_mut::<PostProcessIntensity>().health = 20.0;
+        world.resource_mut::<FireIntensity>().speed = 130.0;
+        world
+            .resource_mut::<Events<PlayMusic>>()
+            .send(PlayMusic::Track(FALLING_TRACKS[1]));
+
+        let mut state = world.resource_mut::<FallingRun>();
+        state.cycle_number = 5;
+        state.segment = 5;
+        state.depth = 900.0;
+        state.score.pickup(130.0);
+        state.since_sample = 0.3;
+        state.history.push(RunSample {
+            speed: 130.0,
+            health: 20.0,
+        });
+        state.dead = true;
+
+        app.update();
+    }
+
+    #[test]
+    fn restart_matches_fresh_launch() {
+        let mut fresh = new_app();
+        switch_state(&mut fresh, AppState::FallingGame);
+        let fresh = run_snapshot(&mut fresh);
+
+        let mut app = new_app();
+        switch_state(&mut app, AppState::FallingGame);
+
+        for _ in 0..2 {
+            play_run(&mut app);
+
+            switch_state(&mut app, AppState::GameOver);
+            assert!(!app.world.contains_resource::<FallingRun>());
+
+            // The `PlayMusic` sent by `play_run` has been dropped by now, only the
+            // one from entering is left, like in the fresh launch
+            switch_state(&mut app, AppState::FallingGame);
+            assert_eq!(run_snapshot(&mut app), fresh);
+        }
+    }
+
+    #[test]
+    fn dying_through_last_teleport_is_game_over() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_state(AppState::FallingGame)
+            .add_event::<PlayerDied>()
+            .add_event::<PlayMusic>()
+            .insert_resource(Shaft::new(0, SHAFT_FLOORS))
+            .insert_resource(FallingRun {
+                cycle_number: STORY_CYCLES,
+                dead: true,
+                ..default()
+            })
+            .add_system_set(
+                SystemSet::on_update(AppState::FallingGame)
+                    .with_system(sys_scene_change.after(PlayerDiedLabel))
+                    .with_system(sys_player_died.label(PlayerDiedLabel)),
+            );
+
+        app.world
+            .resource_mut::<Events<PlayerDied>>()
+            .send(PlayerDied {
+                cause: DeathCause::TerminalVelocity,
+            });
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<State<AppState>>().current(),
+            &AppState::GameOver
+        );
+        assert!(app.world.contains_resource::<DeathReport>());
+        assert!(!app.world.contains_resource::<GameStats>());
+    }
+}
@@ -0,0 +1,260 @@
+use bevy::{
+    asset::HandleId,
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    pbr::MaterialPipeline,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_asset::{PrepareAssetError, RenderAsset},
+        render_resource::{
+            std140::{AsStd140, Std140},
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+            BufferBindingType, BufferInitDescriptor, BufferSize, BufferUsages, ShaderStages,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        RenderApp, RenderStage,
+    },
+};
+
+use crate::shader_reload::FIRE_SHADER_HANDLE;
+
+// Resources
+
+/// Player speed the fire materials burn with, written by the falling game.
+#[derive(Default, Debug)]
+pub(crate) struct FireIntensity {
+    pub speed: f32,
+}
+
+/// Seconds the flames have burned for, sped up with `FireIntensity`.
+#[derive(Default)]
+struct FireClock {
+    seconds: f32,
+}
+
+// GAMEPLAY VARIABLES
+
+const FIRE_MIN_SPEED: f32 = 20.0;
+const FIRE_MAX_SPEED: f32 = 120.0;
+// Flames flicker this many times faster at full speed
+const FIRE_MAX_TIME_SCALE: f32 = 3.0;
+
+// Material
+
+#[derive(Component, Debug, Clone, TypeUuid)]
+#[uuid = "f690fdae-d598-45ab-8225-97e2a3f056e0"]
+pub struct FireMaterial {
+    pub base_color: Color,
+    pub flame_height: f32,
+    pub distortion_level: f32,
+    pub bottom_threshold: f32,
+    /// 0 at rest, 1 at full speed. Driven by `FireIntensity`.
+    pub intensity: f32,
+}
+
+impl Default for FireMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: Color::rgb_linear(1.0, 0.35, 0.05),
+            flame_height: 0.3,
+            distortion_level: 4.0,
+            bottom_threshold: 0.2,
+            intensity: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, AsStd140)]
+struct FireUniform {
+    base_color: Vec4,
+    flame_height: f32,
+    distortion_level: f32,
+    bottom_threshold: f32,
+    intensity: f32,
+}
+
+/// Clock shared by every fire material, so they burn without being touched.
+#[derive(Clone, AsStd140)]
+struct FireGlobalsUniform {
+    time: f32,
+}
+
+/// Render world copy of `FireClock`.
+struct FireTime {
+    seconds: f32,
+}
+
+pub struct FireGlobals {
+    buffer: Buffer,
+}
+
+impl FromWorld for FireGlobals {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            contents: FireGlobalsUniform { time: 0.0 }.as_std140().as_bytes(),
+            label: Some("Fire Globals Buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        Self { buffer }
+    }
+}
+
+#[derive(Clone)]
+pub struct GpuFireMaterial {
+    bind_group: BindGroup,
+}
+
+impl RenderAsset for FireMaterial {
+    type ExtractedAsset = FireMaterial;
+    type PreparedAsset = GpuFireMaterial;
+    type Param = (
+        SRes<RenderDevice>,
+        SRes<MaterialPipeline<Self>>,
+        SRes<FireGlobals>,
+    );
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        extracted_asset: Self::ExtractedAsset,
+        (render_device, material_pipeline, globals): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let value = FireUniform {
+            base_color: extracted_asset.base_color.as_linear_rgba_f32().into(),
+            flame_height: extracted_asset.flame_height,
+            distortion_level: extracted_asset.distortion_level,
+            bottom_threshold: extracted_asset.bottom_threshold,
+            intensity: extracted_asset.intensity,
+        };
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            contents: value.as_std140().as_bytes(),
+            label: Some("Fire Settings Buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: globals.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Fire BindGroup"),
+            layout: &material_pipeline.material_layout,
+        });
+
+        Ok(GpuFireMaterial { bind_group })
+    }
+}
+
+impl Material for FireMaterial {
+    fn vertex_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(FIRE_SHADER_HANDLE.typed())
+    }
+    fn fragment_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(FIRE_SHADER_HANDLE.typed())
+    }
+
+    fn bind_group(render_asset: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &render_asset.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(FireUniform::std140_size_static() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(
+                            FireGlobalsUniform::std140_size_static() as u64
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Fire BindGroup Layout"),
+        })
+    }
+}
+
+// Systems
+
+fn sys_update_fire_material(
+    time: Res<Time>,
+    fire_intensity: Res<FireIntensity>,
+    mut clock: ResMut<FireClock>,
+    mut fire_materials: ResMut<Assets<FireMaterial>>,
+) {
+    let intensity = ((fire_intensity.speed - FIRE_MIN_SPEED) / (FIRE_MAX_SPEED - FIRE_MIN_SPEED))
+        .clamp(0.0, 1.0);
+
+    // Time is accumulated rather than taken from the clock so speeding up doesn't make the flames jump
+    clock.seconds += time.delta_seconds() * (1.0 + (FIRE_MAX_TIME_SCALE - 1.0) * intensity);
+
+    // Mutable access re-uploads the material, so only touch the ones that are off
+    let stale: Vec<HandleId> = fire_materials
+        .iter()
+        .filter(|(_, material)| material.intensity != intensity)
+        .map(|(id, _)| id)
+        .collect();
+
+    for id in stale {
+        if let Some(material) = fire_materials.get_mut(id) {
+            material.intensity = intensity;
+        }
+    }
+}
+
+fn extract_fire_time(mut commands: Commands, clock: Res<FireClock>) {
+    commands.insert_resource(FireTime {
+        seconds: clock.seconds,
+    });
+}
+
+fn prepare_fire_globals(
+    time: Res<FireTime>,
+    globals: Res<FireGlobals>,
+    render_queue: Res<RenderQueue>,
+) {
+    let value = FireGlobalsUniform { time: time.seconds };
+    render_queue.write_buffer(&globals.buffer, 0, value.as_std140().as_bytes());
+}
+
+// Plugins
+
+pub struct FirePlugin;
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<FireMaterial>::default())
+            .init_resource::<FireIntensity>()
+            .init_resource::<FireClock>()
+            .add_system(sys_update_fire_material);
+        // The falling game tests run it without a renderer
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<FireGlobals>()
+                .add_system_to_stage(RenderStage::Extract, extract_fire_time)
+                .add_system_to_stage(RenderStage::Prepare, prepare_fire_globals);
+        }
+    }
+}
@@ -1,33 +1,91 @@
 use crate::app_states::*;
+use crate::audio::*;
+use crate::endless::*;
+use crate::leaderboard::*;
+use crate::loading::*;
+use crate::state_scoped::*;
+use crate::time_attack::*;
+use crate::widgets::*;
 use bevy::prelude::*;
-use bevy_kira_audio::Audio;
 
 // Components
 
-#[derive(Component, Default)]
-pub struct Interface;
-
 #[derive(Component, Default)]
 pub struct GameOverText;
 
 // Resources
 
+/// Result of a finished run, inserted by the falling game right before `GameEnd`.
 pub struct GameStats {
     pub time: u32,
+    pub assisted: bool,
+    pub mode: RunMode,
+    pub depth: u32,
+    pub splits: Vec<Split>,
+    pub score: u32,
 }
 
-// HUD
+/// What the finished run was recorded into, so the HUD only has to read it.
+#[derive(Default)]
+struct RunRecords {
+    leaderboard: Leaderboard,
+    /// Best splits from before this run, the ones its splits are shown against.
+    best_splits: BestSplits,
+}
 
-fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>, stats: Res<GameStats>) {
-    commands.spawn_bundle(UiCameraBundle::default());
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct RecordRunLabel;
+
+// Systems
+
+fn sys_record_run(stats: Res<GameStats>, mut records: ResMut<RunRecords>) {
+    let mut leaderboard = Leaderboard::load(stats.mode);
+    leaderboard.record(LeaderboardEntry {
+        time: stats.time,
+        assisted: stats.assisted,
+        depth: stats.depth,
+    });
+    leaderboard.save();
+
+    let mut best_splits = BestSplits::load();
+    records.best_splits = best_splits.clone();
+    if stats.mode == RunMode::TimeAttack {
+        best_splits.record(&stats.splits);
+        best_splits.save();
+    }
 
-    commands.insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)));
+    records.leaderboard = leaderboard;
+}
 
-    let font = asset_server.load("fonts/ARCADECLASSIC.ttf");
-    let game_over_text = Text::with_section(
-        format!("FINISHED  IN  {}  SECONDS", stats.time),
+// HUD
+
+fn sys_draw_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    stats: Res<GameStats>,
+    records: Res<RunRecords>,
+    mut music: EventWriter<PlayMusic>,
+) {
+    music.send(PlayMusic::Silence);
+
+    let font = asset_server.load(FONT);
+
+    let leaderboard = &records.leaderboard;
+    let table = match stats.mode {
+        RunMode::TimeAttack => split_table(&stats.splits, &records.best_splits),
+        _ => leaderboard
+            .entries
+            .iter()
+            .take(5)
+            .enumerate()
+            .map(|(i, entry)| format!("{}   {}\n", i + 1, entry.describe(leaderboard.mode)))
+            .collect::<String>(),
+    };
+
+    let leaderboard_text = Text::with_section(
+        table,
         TextStyle {
-            font_size: 65.0,
+            font_size: 20.0,
             font: font.clone(),
             color: Color::WHITE,
         },
@@ -37,10 +95,24 @@ fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>, stats: R
         },
     );
 
-    let credits_text = Text::with_section(
-        "artwork  by  biboran  artists\na  theme  for  a  murder  font  by  Livin  Hell\nARCADE  font  by  anonymous\nDichotomy  and  Absolom  tracks  by  Electric  Senses\nMade  on  Bevy  with  Rust",
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(StateScoped(AppState::GameEnd));
+
+    commands.insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)));
+
+    let title = match stats.mode {
+        RunMode::Endless => format!("FELL  {}  DEEP", stats.depth),
+        _ => format!(
+            "FINISHED  IN  {}  SECONDS\nSCORE  {}",
+            stats.time, stats.score
+        ),
+    };
+
+    let game_over_text = Text::with_section(
+        title,
         TextStyle {
-            font_size: 12.0,
+            font_size: 65.0,
             font: font.clone(),
             color: Color::WHITE,
         },
@@ -65,7 +137,7 @@ fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>, stats: R
             visibility: Visibility { is_visible: false },
             ..Default::default()
         })
-        .insert(Interface)
+        .insert(StateScoped(AppState::GameEnd))
         .with_children(|parent| {
             parent
                 .spawn_bundle(TextBundle {
@@ -85,12 +157,12 @@ fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>, stats: R
 
             parent
                 .spawn_bundle(TextBundle {
-                    text: credits_text.clone(),
+                    text: leaderboard_text.clone(),
                     style: Style {
                         position_type: PositionType::Absolute,
                         justify_content: JustifyContent::Center,
                         position: Rect {
-                            top: Val::Px(650.0),
+                            top: Val::Px(450.0),
                             ..Default::default()
                         },
                         ..Default::default()
@@ -99,83 +171,46 @@ fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>, stats: R
                 })
                 .insert(GameOverText);
 
-            parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(170.0), Val::Px(65.0)),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        position: Rect {
-                            top: Val::Px(50.0),
-                            ..Default::default()
-                        },
-                        margin: Rect {
-                            top: Val::Px(60.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    color: Color::rgb(0.6, 0.1, 0.1).into(),
-                    ..Default::default()
-                })
-                .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "START OVER",
-                            TextStyle {
-                                font: asset_server.load("fonts/ARCADECLASSIC.TTF"),
-                                font_size: 20.0,
-                                color: Color::WHITE.into(),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    });
-                });
+            spawn_button(
+                parent,
+                &asset_server,
+                "START OVER",
+                MenuAction::Restart,
+                WidgetSize::Large,
+            );
         });
 }
 
-const NORMAL_BUTTON: Color = Color::rgb(0.65, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.75, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(1.0, 0.35, 0.25);
+/// Splits of the run against the best ones before it.
+fn split_table(splits: &[Split], best_splits: &BestSplits) -> String {
+    splits
+        .iter()
+        .map(|split| match best_splits.best(split.cycle) {
+            Some(best) => format!(
+                "cycle  {}   {:.2}   {:+.2}\n",
+                split.cycle + 1,
+                split.time,
+                split.time - best
+            ),
+            None => format!("cycle  {}   {:.2}   new\n", split.cycle + 1, split.time),
+        })
+        .collect()
+}
 
-fn sys_button_new_game(
-    mut interaction_query: Query<
-        (&Interaction, &mut UiColor, &Children),
-        (Changed<Interaction>, With<Button>),
-    >,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>,
+fn sys_menu_actions(
+    mut activated: EventReader<ButtonActivated>,
     mut app_state: ResMut<State<AppState>>,
+    mut loading_settings: ResMut<LoadingSettings>,
 ) {
-    for (interaction, mut color, _) in interaction_query.iter_mut() {
-        match *interaction {
-            Interaction::Clicked => {
-                audio.play(asset_server.load("music/click.mp3"));
-                *color = PRESSED_BUTTON.into();
-                app_state.set(AppState::FallingGame).unwrap();
-            }
-            Interaction::Hovered => {
-                audio.play(asset_server.load("music/hover.mp3"));
-                *color = HOVERED_BUTTON.into();
-            }
-            Interaction::None => {
-                *color = NORMAL_BUTTON.into();
-            }
+    for ButtonActivated(action) in activated.iter() {
+        if *action == MenuAction::Restart {
+            load_state(&mut app_state, &mut loading_settings, AppState::FallingGame);
         }
     }
 }
 
-fn sys_clear_entities(
-    mut commands: Commands,
-    audio: Res<Audio>,
-    mut main_menu_components: Query<Entity, With<Interface>>,
-) {
-    for e in main_menu_components.iter_mut() {
-        commands.entity(e).despawn_recursive();
-    }
-
-    audio.stop();
+fn sys_clear_stats(mut commands: Commands) {
+    commands.remove_resource::<GameStats>();
 }
 
 // Plugins
@@ -183,10 +218,13 @@ fn sys_clear_entities(
 pub struct GameEndPlugin;
 impl Plugin for GameEndPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(AppState::GameEnd).with_system(sys_draw_hud))
-            .add_system_set(SystemSet::on_exit(AppState::GameEnd).with_system(sys_clear_entities))
+        app.init_resource::<RunRecords>()
             .add_system_set(
-                SystemSet::on_update(AppState::GameEnd).with_system(sys_button_new_game),
-            );
+                SystemSet::on_enter(AppState::GameEnd)
+                    .with_system(sys_record_run.label(RecordRunLabel))
+                    .with_system(sys_draw_hud.after(RecordRunLabel)),
+            )
+            .add_system_set(SystemSet::on_update(AppState::GameEnd).with_system(sys_menu_actions))
+            .add_system_set(SystemSet::on_exit(AppState::GameEnd).with_system(sys_clear_stats));
     }
 }
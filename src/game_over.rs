@@ -1,35 +1,165 @@
 use crate::app_states::*;
+use crate::audio::*;
+use crate::bloodfield::*;
+use crate::checkpoint::*;
+use crate::death::*;
+use crate::endless::*;
+use crate::falling::*;
+use crate::loading::*;
+use crate::main_menu::*;
+use crate::state_scoped::*;
+use crate::widgets::*;
 use bevy::prelude::*;
-use bevy_kira_audio::Audio;
 
 // Components
 
-#[derive(Component, Default)]
-pub struct Interface;
-
 #[derive(Component, Default)]
 pub struct GameOverText;
 
+// GAMEPLAY VARIABLES
+
+const GRAPH_COLUMNS: usize = 60;
+const GRAPH_WIDTH: f32 = 360.0;
+const GRAPH_HEIGHT: f32 = 90.0;
+const GRAPH_SPEED_COLOR: Color = Color::rgb(0.6, 0.1, 0.1);
+const GRAPH_HEALTH_COLOR: Color = Color::rgb(0.2, 0.7, 0.1);
+
 // HUD
 
-fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn_bundle(UiCameraBundle::default());
+fn run_summary(report: &DeathReport) -> String {
+    let progress = match report.mode {
+        RunMode::Endless => format!("{}  deep", report.depth),
+        _ => format!("cycle  {}   {}  deep", report.cycle + 1, report.depth),
+    };
 
-    commands.insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)));
+    format!(
+        "{}\n{}  seconds   {}  pickups\npeak  speed  {}",
+        progress, report.time, report.pickups, report.top_speed as u32
+    )
+}
+
+/// Speed as bars and health as a line over the whole run, squeezed into a fixed number
+/// of columns.
+fn spawn_graph(parent: &mut ChildBuilder, history: &[RunSample]) {
+    let max_speed = history
+        .iter()
+        .map(|sample| sample.speed)
+        .fold(DAMAGE_SPEED * 1.5, f32::max);
+    let max_health = history
+        .iter()
+        .map(|sample| sample.health)
+        .fold(100.0, f32::max);
+
+    let columns = history.len().min(GRAPH_COLUMNS);
+    let column_width = 100.0 / GRAPH_COLUMNS as f32;
 
-    let font = asset_server.load("fonts/ARCADECLASSIC.ttf");
-    let game_over_text = Text::with_section(
-        "game over",
-        TextStyle {
-            font_size: 65.0,
-            font: font.clone(),
-            color: Color::WHITE,
-        },
-        TextAlignment {
-            horizontal: HorizontalAlign::Center,
+    let mut graph = panel_bundle();
+    graph.style.size = Size::new(Val::Px(GRAPH_WIDTH), Val::Px(GRAPH_HEIGHT));
+    graph.style.padding = Rect::all(Val::Px(0.0));
+    graph.style.margin = Rect::all(Val::Px(10.0));
+
+    parent.spawn_bundle(graph).with_children(|parent| {
+        let column = |left: f32, bottom: f32, height: Val, color: Color| NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(left),
+                    bottom: Val::Percent(bottom),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(column_width), height),
+                ..Default::default()
+            },
+            color: color.into(),
             ..Default::default()
-        },
+        };
+
+        for i in 0..columns {
+            let start = i * history.len() / columns;
+            let end = ((i + 1) * history.len() / columns).max(start + 1);
+            let bucket = &history[start..end];
+
+            let speed = bucket.iter().map(|s| s.speed).fold(0.0, f32::max);
+            let health = bucket.iter().map(|s| s.health).fold(f32::MAX, f32::min);
+            let left = i as f32 * column_width;
+
+            parent.spawn_bundle(column(
+                left,
+                0.0,
+                Val::Percent((speed / max_speed).clamp(0.0, 1.0) * 100.0),
+                GRAPH_SPEED_COLOR,
+            ));
+            parent.spawn_bundle(column(
+                left,
+                (health / max_health).clamp(0.0, 1.0) * 100.0,
+                Val::Px(3.0),
+                GRAPH_HEALTH_COLOR,
+            ));
+        }
+
+        // Where falling starts to hurt
+        let mut threshold = column(
+            0.0,
+            DAMAGE_SPEED / max_speed * 100.0,
+            Val::Px(1.0),
+            Color::rgba(1.0, 1.0, 1.0, 0.4),
+        );
+        threshold.style.size.width = Val::Percent(100.0);
+        parent.spawn_bundle(threshold);
+    });
+}
+
+fn death_stats_line(stats: &DeathStats) -> String {
+    match stats.most_common() {
+        Some((cause, count)) => format!(
+            "most  runs  end  by  {}   {}  of  {}",
+            cause.title().to_lowercase(),
+            count,
+            stats.total()
+        ),
+        None => String::new(),
+    }
+}
+
+fn sys_draw_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    report: Option<Res<DeathReport>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut bmaterials: ResMut<Assets<BloodfieldMaterial>>,
+    run_options: Res<RunOptions>,
+    mut music: EventWriter<PlayMusic>,
+) {
+    music.send(PlayMusic::Silence);
+
+    // Checkpoints belong to the story, other modes have nothing to continue
+    let checkpoint = match run_options.mode {
+        RunMode::Story => Checkpoint::load(),
+        _ => None,
+    };
+
+    let death_stats = DeathStats::load();
+
+    let camera = spawn_menu_camera(&mut commands);
+    commands
+        .entity(camera)
+        .insert(StateScoped(AppState::GameOver));
+
+    let backdrop = spawn_bloodfield_backdrop(
+        &mut commands,
+        &mut meshes,
+        &mut bmaterials,
+        asset_server.load("bloodfield/game_over.bloodfield"),
     );
+    commands
+        .entity(backdrop)
+        .insert(StateScoped(AppState::GameOver));
+
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(StateScoped(AppState::GameOver));
+
+    commands.insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)));
 
     commands
         .spawn_bundle(NodeBundle {
@@ -38,109 +168,114 @@ fn sys_draw_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
                     width: Val::Percent(100.0),
                     height: Val::Percent(100.0),
                 },
-                align_self: AlignSelf::FlexEnd,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
                 ..Default::default()
             },
-            visibility: Visibility { is_visible: false },
+            color: Color::NONE.into(),
             ..Default::default()
         })
-        .insert(Interface)
+        .insert(StateScoped(AppState::GameOver))
         .with_children(|parent| {
             parent
-                .spawn_bundle(TextBundle {
-                    text: game_over_text.clone(),
-                    style: Style {
-                        position_type: PositionType::Absolute,
-                        justify_content: JustifyContent::Center,
-                        // position: Rect {
-                        //     top: Val::Px(450.0),
-                        //     ..Default::default()
-                        // },
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
+                .spawn_bundle(label_bundle(&asset_server, "game over", 65.0, Color::WHITE))
                 .insert(GameOverText);
 
+            if let Some(report) = report.as_ref() {
+                parent.spawn_bundle(label_bundle(
+                    &asset_server,
+                    format!("killed  by  {}", report.cause.title()),
+                    25.0,
+                    Color::rgb(0.9, 0.2, 0.2),
+                ));
+
+                parent.spawn_bundle(label_bundle(
+                    &asset_server,
+                    run_summary(report),
+                    18.0,
+                    Color::WHITE,
+                ));
+
+                spawn_graph(parent, &report.history);
+            }
+
             parent
-                .spawn_bundle(ButtonBundle {
+                .spawn_bundle(NodeBundle {
                     style: Style {
-                        size: Size::new(Val::Px(170.0), Val::Px(65.0)),
-                        justify_content: JustifyContent::Center,
+                        flex_direction: FlexDirection::Row,
                         align_items: AlignItems::Center,
-                        position: Rect {
-                            top: Val::Px(50.0),
-                            ..Default::default()
-                        },
-                        margin: Rect {
-                            top: Val::Px(60.0),
-                            ..Default::default()
-                        },
                         ..Default::default()
                     },
-                    color: Color::rgb(0.6, 0.1, 0.1).into(),
+                    color: Color::NONE.into(),
                     ..Default::default()
                 })
                 .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "CONFESS",
-                            TextStyle {
-                                font: asset_server.load("fonts/ARCADECLASSIC.TTF"),
-                                font_size: 20.0,
-                                color: Color::WHITE.into(),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    });
+                    spawn_button(
+                        parent,
+                        &asset_server,
+                        "RETRY",
+                        MenuAction::Restart,
+                        WidgetSize::Large,
+                    );
+
+                    if let Some(checkpoint) = checkpoint {
+                        spawn_button(
+                            parent,
+                            &asset_server,
+                            format!("RETRY  FROM  CYCLE  {}", checkpoint.cycle_number + 1),
+                            MenuAction::ContinueFromCheckpoint,
+                            WidgetSize::Large,
+                        );
+                    }
+
+                    spawn_button(
+                        parent,
+                        &asset_server,
+                        "MAIN  MENU",
+                        MenuAction::MainMenu,
+                        WidgetSize::Large,
+                    );
                 });
+
+            parent.spawn_bundle(label_bundle(
+                &asset_server,
+                death_stats_line(&death_stats),
+                14.0,
+                Color::rgb(0.7, 0.7, 0.7),
+            ));
         });
 }
 
-const NORMAL_BUTTON: Color = Color::rgb(0.65, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.75, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(1.0, 0.35, 0.25);
-
-fn sys_button_new_game(
-    mut interaction_query: Query<
-        (&Interaction, &mut UiColor, &Children),
-        (Changed<Interaction>, With<Button>),
-    >,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>,
+fn sys_menu_actions(
+    mut activated: EventReader<ButtonActivated>,
     mut app_state: ResMut<State<AppState>>,
+    mut loading_settings: ResMut<LoadingSettings>,
+    mut run_options: ResMut<RunOptions>,
 ) {
-    for (interaction, mut color, _) in interaction_query.iter_mut() {
-        match *interaction {
-            Interaction::Clicked => {
-                audio.play(asset_server.load("music/click.mp3"));
-                *color = PRESSED_BUTTON.into();
-                app_state.set(AppState::FallingGame).unwrap();
+    for ButtonActivated(action) in activated.iter() {
+        match *action {
+            MenuAction::Restart => {
+                run_options.continue_from_checkpoint = false;
             }
-            Interaction::Hovered => {
-                audio.play(asset_server.load("music/hover.mp3"));
-                *color = HOVERED_BUTTON.into();
+            // Checkpoints are only ever saved by the story
+            MenuAction::ContinueFromCheckpoint => {
+                run_options.continue_from_checkpoint = true;
+                run_options.mode = RunMode::Story;
             }
-            Interaction::None => {
-                *color = NORMAL_BUTTON.into();
+            MenuAction::MainMenu => {
+                load_state(&mut app_state, &mut loading_settings, AppState::MainMenu);
+                continue;
             }
+            _ => continue,
         }
-    }
-}
 
-fn sys_clear_entities(
-    mut commands: Commands,
-    audio: Res<Audio>,
-    mut main_menu_components: Query<Entity, With<Interface>>,
-) {
-    for e in main_menu_components.iter_mut() {
-        commands.entity(e).despawn_recursive();
+        load_state(&mut app_state, &mut loading_settings, AppState::FallingGame);
     }
+}
 
-    audio.stop();
+fn sys_clear_report(mut commands: Commands) {
+    commands.remove_resource::<DeathReport>();
 }
 
 // Plugins
@@ -149,9 +284,7 @@ pub struct GameOverScreenPlugin;
 impl Plugin for GameOverScreenPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(sys_draw_hud))
-            .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(sys_clear_entities))
-            .add_system_set(
-                SystemSet::on_update(AppState::GameOver).with_system(sys_button_new_game),
-            );
+            .add_system_set(SystemSet::on_update(AppState::GameOver).with_system(sys_menu_actions))
+            .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(sys_clear_report));
     }
 }
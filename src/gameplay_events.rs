@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+use heron::*;
+
+use crate::app_states::*;
+use crate::death::*;
+use crate::falling::*;
+
+// Events
+
+pub(crate) struct PickupCollected {
+    pub kind: CubeType,
+    pub entity: Entity,
+    pub position: Vec3,
+}
+
+/// Sent with the cycle the teleport leads into.
+pub(crate) struct TeleportEntered {
+    pub cycle: u8,
+}
+
+pub(crate) struct DamageTaken {
+    pub amount: f32,
+}
+
+/// Sent with the cause of the damage that killed the player.
+pub(crate) struct PlayerDied {
+    pub cause: DeathCause,
+}
+
+/// Sent when a story or time attack run takes its last teleport.
+pub(crate) struct RunFinished {
+    pub health: f32,
+    pub braked: bool,
+    /// Cycle the run was started from, see `RunOptions::start_cycle`.
+    pub start_cycle: u8,
+    /// Whether the run was picked up from a checkpoint.
+    pub continued: bool,
+}
+
+/// Sent whenever the run goes faster than it has so far.
+pub(crate) struct TopSpeedReached {
+    pub speed: f32,
+}
+
+/// Sent when the player gets out of the damage zone alive.
+pub(crate) struct DamageZoneLeft {
+    pub seconds: f32,
+}
+
+/// Subscribers ordered after this handle the events of the frame they happened in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub(crate) struct GameplayEventsLabel;
+
+// Systems
+
+/// The only reader of heron's collision events, everything else subscribes to what they
+/// turn into.
+fn sys_classify_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    state: Res<FallingRun>,
+    query_cubes: Query<(&Transform, &Cube)>,
+    mut pickups: EventWriter<PickupCollected>,
+    mut teleports: EventWriter<TeleportEntered>,
+) {
+    fn is_player(layers: CollisionLayers) -> bool {
+        layers.contains_group(Layer::Player)
+    }
+
+    let mut teleported = false;
+    let mut collected = Vec::new();
+
+    // A contact stopping is not a second hit
+    for event in collision_events.iter().filter(|event| event.is_started()) {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (layers_1, layers_2) = event.collision_layers();
+
+        let (other, layers) = match (is_player(layers_1), is_player(layers_2)) {
+            (true, false) => (entity_2, layers_2),
+            (false, true) => (entity_1, layers_1),
+            _ => continue,
+        };
+
+        if layers.contains_group(Layer::World) {
+            // Pickups already despawned this frame are simply gone from the query
+            if let Ok((transform, cube)) = query_cubes.get(other) {
+                collected.push(PickupCollected {
+                    kind: cube.cube_type,
+                    entity: other,
+                    position: transform.translation,
+                });
+            }
+        } else if layers.contains_group(Layer::Teleport) && !teleported {
+            teleported = true;
+            teleports.send(TeleportEntered {
+                cycle: state.cycle_number.saturating_add(1),
+            });
+        }
+    }
+
+    // Every pickup once, in the order a fall reaches them whatever order physics reported
+    collected.sort_by(|a, b| {
+        b.position
+            .y
+            .total_cmp(&a.position.y)
+            .then(a.entity.cmp(&b.entity))
+    });
+    collected.dedup_by_key(|pickup| pickup.entity);
+    pickups.send_batch(collected.into_iter());
+}
+
+// Plugins
+
+pub struct GameplayEventsPlugin;
+impl Plugin for GameplayEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PickupCollected>()
+            .add_event::<TeleportEntered>()
+            .add_event::<DamageTaken>()
+            .add_event::<PlayerDied>()
+            .add_event::<RunFinished>()
+            .add_event::<TopSpeedReached>()
+            .add_event::<DamageZoneLeft>()
+            .add_system_set(
+                SystemSet::on_update(AppState::FallingGame)
+                    .with_system(sys_classify_collisions.label(GameplayEventsLabel)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::Events;
+
+    fn player_data(player: Entity) -> CollisionData {
+        CollisionData::new(
+            player,
+            player,
+            CollisionLayers::new(Layer::Player, Layer::World),
+            Vec::new(),
+        )
+    }
+
+    fn pickup_data(pickup: Entity) -> CollisionData {
+        CollisionData::new(
+            pickup,
+            pickup,
+            CollisionLayers::new(Layer::World, Layer::Player),
+            Vec::new(),
+        )
+    }
+
+    fn spawn_pickup(app: &mut App, y: f32) -> Entity {
+        app.world
+            .spawn()
+            .insert(Transform::from_xyz(0.0, y, 0.0))
+            .insert(Cube {
+                cube_type: CubeType::Health,
+            })
+            .id()
+    }
+
+    #[test]
+    fn every_pickup_collected_once_from_the_top() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_state(AppState::FallingGame)
+            .add_event::<CollisionEvent>()
+            .insert_resource(FallingRun::default())
+            .add_plugin(GameplayEventsPlugin);
+
+        let player = app.world.spawn().id();
+        let low = spawn_pickup(&mut app, 10.0);
+        let high = spawn_pickup(&mut app, 30.0);
+        let middle = spawn_pickup(&mut app, 20.0);
+        let gone = spawn_pickup(&mut app, 40.0);
+        app.world.despawn(gone);
+
+        let mut collisions = app.world.resource_mut::<Events<CollisionEvent>>();
+        collisions.send(CollisionEvent::Started(
+            player_data(player),
+            pickup_data(low),
+        ));
+        collisions.send(CollisionEvent::Started(
+            player_data(player),
+            pickup_data(middle),
+        ));
+        collisions.send(CollisionEvent::Started(
+            player_data(player),
+            pickup_data(gone),
+        ));
+        collisions.send(CollisionEvent::Started(
+            player_data(player),
+            pickup_data(low),
+        ));
+        // Physics reports pairs either way round
+        collisions.send(CollisionEvent::Started(
+            pickup_data(high),
+            player_data(player),
+        ));
+        app.update();
+
+        let events = app.world.resource::<Events<PickupCollected>>();
+        let collected: Vec<Entity> = events
+            .get_reader()
+            .iter(events)
+            .map(|pickup| pickup.entity)
+            .collect();
+        assert_eq!(collected, vec![high, middle, low]);
+    }
+}
@@ -0,0 +1,455 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use heron::*;
+
+use crate::app_states::*;
+use crate::checkpoint::*;
+use crate::endless::*;
+use crate::falling::*;
+use crate::gameplay_events::*;
+use crate::indoctrination::*;
+use crate::score::*;
+use crate::state_scoped::*;
+use crate::time_attack::*;
+use crate::widgets::*;
+
+// Components
+
+#[derive(Component, Default)]
+pub struct VelocityText;
+
+#[derive(Component, Default)]
+pub struct SpeedNeedle;
+
+#[derive(Component, Default)]
+pub struct HealthText;
+
+#[derive(Component, Default)]
+pub struct HealthBar;
+
+#[derive(Component, Default)]
+pub struct StopwatchText;
+
+#[derive(Component, Default)]
+pub struct CycleBar;
+
+/// Cycle of the story, or depth of an endless run.
+#[derive(Component, Default)]
+pub struct DistanceText;
+
+#[derive(Component, Default)]
+pub struct EffectsText;
+
+#[derive(Component, Default)]
+pub struct DangerText;
+
+// Resources
+
+/// Animation state of the HUD, reset whenever the falling game is entered.
+#[derive(Default)]
+struct HudState {
+    shown_speed: f32,
+    flash: f32,
+    recent: Vec<(&'static str, f32)>,
+}
+
+// GAMEPLAY VARIABLES
+
+/// Speed at the end of the dial.
+const DIAL_SPEED: f32 = 200.0;
+/// The needle sweeps this far to either side of straight up.
+const DIAL_ANGLE: f32 = PI * 2.0 / 3.0;
+const NEEDLE_SMOOTHING: f32 = 8.0;
+/// The warning shows from this share of the damage speed on.
+const DANGER_SHARE: f32 = 0.8;
+const FLASH_SECONDS: f32 = 0.25;
+const EFFECT_SECONDS: f32 = 2.5;
+
+const HEALTH_COLOR: Color = Color::rgb(0.2, 0.7, 0.1);
+const OVERHEALTH_COLOR: Color = Color::rgb(0.4, 0.9, 0.9);
+const FLASH_COLOR: Color = Color::rgb(1.0, 0.9, 0.9);
+const DANGER_COLOR: Color = Color::rgb(1.0, 0.15, 0.1);
+
+// Systems
+
+fn hud_text(font: &Handle<Font>, font_size: f32, color: Color) -> Text {
+    Text::with_section(
+        "",
+        TextStyle {
+            font_size,
+            font: font.clone(),
+            color,
+        },
+        TextAlignment {
+            horizontal: HorizontalAlign::Center,
+            ..Default::default()
+        },
+    )
+}
+
+fn absolute(left: f32, top: f32, width: f32, height: f32) -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        position: Rect {
+            left: Val::Percent(left),
+            top: Val::Percent(top),
+            ..Default::default()
+        },
+        size: Size::new(Val::Percent(width), Val::Percent(height)),
+        ..Default::default()
+    }
+}
+
+fn sys_draw_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    run_options: Res<RunOptions>,
+    mut hud: ResMut<HudState>,
+) {
+    *hud = HudState::default();
+
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(StateScoped(AppState::FallingGame));
+
+    let font = asset_server.load(FONT);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(StateScoped(AppState::FallingGame))
+        .with_children(|parent| {
+            // Health, top left
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: absolute(3.0, 4.0, 25.0, 3.0),
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: absolute(0.0, 0.0, 100.0, 100.0),
+                            color: HEALTH_COLOR.into(),
+                            ..Default::default()
+                        })
+                        .insert(HealthBar);
+                });
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: hud_text(&font, 20.0, Color::WHITE),
+                    style: absolute(3.0, 8.0, 25.0, 4.0),
+                    ..Default::default()
+                })
+                .insert(HealthText);
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: hud_text(&font, 18.0, Color::WHITE),
+                    style: absolute(3.0, 14.0, 25.0, 30.0),
+                    ..Default::default()
+                })
+                .insert(EffectsText);
+
+            // Time and score, top centre
+            parent
+                .spawn_bundle(TextBundle {
+                    text: hud_text(&font, 30.0, Color::WHITE),
+                    style: absolute(40.0, 3.0, 20.0, 5.0),
+                    ..Default::default()
+                })
+                .insert(StopwatchText);
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: hud_text(&font, 25.0, Color::YELLOW),
+                    style: absolute(40.0, 9.0, 20.0, 5.0),
+                    ..Default::default()
+                })
+                .insert(ScoreText);
+
+            if run_options.mode == RunMode::TimeAttack {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: hud_text(&font, 25.0, Color::WHITE),
+                        style: absolute(40.0, 14.0, 20.0, 5.0),
+                        ..Default::default()
+                    })
+                    .insert(SplitText);
+            }
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: hud_text(&font, 45.0, DANGER_COLOR),
+                    style: absolute(35.0, 30.0, 30.0, 8.0),
+                    ..Default::default()
+                })
+                .insert(DangerText);
+
+            // Progress through the shaft, right edge
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: absolute(95.0, 10.0, 1.5, 75.0),
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: absolute(0.0, 0.0, 100.0, 0.0),
+                            color: Color::rgb(0.8, 0.1, 0.1).into(),
+                            ..Default::default()
+                        })
+                        .insert(CycleBar);
+                });
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: hud_text(&font, 20.0, Color::WHITE),
+                    style: absolute(82.0, 87.0, 16.0, 5.0),
+                    ..Default::default()
+                })
+                .insert(DistanceText);
+
+            // Speedometer, bottom centre
+            parent
+                .spawn_bundle(ImageBundle {
+                    style: absolute(40.0, 62.0, 20.0, 27.0),
+                    image: asset_server.load("images/speedometer.png").into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    // Sized so its centre, which it turns around, is the centre of the dial
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: absolute(0.0, 0.0, 100.0, 124.0),
+                            color: Color::NONE.into(),
+                            ..Default::default()
+                        })
+                        .insert(SpeedNeedle)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(NodeBundle {
+                                style: absolute(49.0, 15.0, 2.0, 35.0),
+                                color: DANGER_COLOR.into(),
+                                ..Default::default()
+                            });
+                        });
+
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: hud_text(&font, 30.0, Color::WHITE),
+                            style: absolute(30.0, 70.0, 40.0, 25.0),
+                            ..Default::default()
+                        })
+                        .insert(VelocityText);
+                });
+        });
+}
+
+fn sys_update_speedometer(
+    time: Res<Time>,
+    mut hud: ResMut<HudState>,
+    query_actor: Query<&Velocity, With<Actor>>,
+    mut query_needle: Query<&mut Transform, With<SpeedNeedle>>,
+    mut query_text: Query<&mut Text, With<VelocityText>>,
+) {
+    let speed = match query_actor.iter().next() {
+        Some(velocity) => -velocity.linear.y,
+        None => return,
+    };
+
+    let smoothing = (NEEDLE_SMOOTHING * time.delta_seconds()).min(1.0);
+    hud.shown_speed += (speed - hud.shown_speed) * smoothing;
+
+    let share = (hud.shown_speed.abs() / DIAL_SPEED).min(1.0);
+    for mut transform in query_needle.iter_mut() {
+        transform.rotation = Quat::from_rotation_z(DIAL_ANGLE * (1.0 - 2.0 * share));
+    }
+
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = format!("{}", speed as i32);
+        text.sections[0].style.color = match speed > DAMAGE_SPEED {
+            true => DANGER_COLOR,
+            false => Color::WHITE,
+        };
+    }
+}
+
+fn sys_update_health(
+    time: Res<Time>,
+    mut hud: ResMut<HudState>,
+    mut damage_taken: EventReader<DamageTaken>,
+    query_actor: Query<&Actor>,
+    mut query_bar: Query<(&mut Style, &mut UiColor), With<HealthBar>>,
+    mut query_text: Query<&mut Text, With<HealthText>>,
+) {
+    if damage_taken.iter().count() > 0 {
+        hud.flash = FLASH_SECONDS;
+    }
+    hud.flash = (hud.flash - time.delta_seconds()).max(0.0);
+
+    let health = match query_actor.iter().next() {
+        Some(actor) => actor.health,
+        None => return,
+    };
+
+    for (mut style, mut color) in query_bar.iter_mut() {
+        style.size.width = Val::Percent(health.clamp(0.0, 100.0));
+
+        let base = match health > 100.0 {
+            true => OVERHEALTH_COLOR,
+            false => HEALTH_COLOR,
+        };
+        let t = hud.flash / FLASH_SECONDS;
+        *color = Color::rgb(
+            base.r() + (FLASH_COLOR.r() - base.r()) * t,
+            base.g() + (FLASH_COLOR.g() - base.g()) * t,
+            base.b() + (FLASH_COLOR.b() - base.b()) * t,
+        )
+        .into();
+    }
+
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = format!("health   {}", health as i32);
+    }
+}
+
+fn sys_update_progress(
+    state: Res<FallingRun>,
+    query_actor: Query<&Transform, With<Actor>>,
+    mut query_bar: Query<&mut Style, With<CycleBar>>,
+    mut query_text: Query<&mut Text, (With<DistanceText>, Without<StopwatchText>)>,
+    mut query_stopwatch: Query<&mut Text, With<StopwatchText>>,
+) {
+    let y = match query_actor.iter().next() {
+        Some(transform) => transform.translation.y,
+        None => return,
+    };
+
+    // Filled top down as the player falls towards the teleport
+    let fallen = ((SEGMENT_DEPTH - y) / SEGMENT_DEPTH).clamp(0.0, 1.0);
+    for mut style in query_bar.iter_mut() {
+        style.size.height = Val::Percent(fallen * 100.0);
+    }
+
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = match state.mode {
+            RunMode::Endless => format!("depth   {}", state.depth as u32),
+            _ => format!(
+                "cycle   {} of {}",
+                (state.cycle_number + 1).min(STORY_CYCLES),
+                STORY_CYCLES
+            ),
+        };
+    }
+
+    for mut text in query_stopwatch.iter_mut() {
+        text.sections[0].value = format!("{} sec", state.elapsed_secs() as i32);
+    }
+}
+
+fn sys_update_effects(
+    time: Res<Time>,
+    mut hud: ResMut<HudState>,
+    mut pickups: EventReader<PickupCollected>,
+    state: Res<FallingRun>,
+    indoctrination: Option<Res<IndoctrinationSettings>>,
+    query_actor: Query<(&Actor, &Velocity)>,
+    mut query_text: Query<&mut Text, With<EffectsText>>,
+) {
+    for pickup in pickups.iter() {
+        let label = match pickup.kind {
+            CubeType::Health => "health  up",
+            CubeType::Speed => "speed  up",
+            CubeType::Brake => "slowed  down",
+            CubeType::Environment => continue,
+        };
+        hud.recent.push((label, EFFECT_SECONDS));
+    }
+
+    for (_, left) in hud.recent.iter_mut() {
+        *left -= time.delta_seconds();
+    }
+    hud.recent.retain(|(_, left)| *left > 0.0);
+
+    let mut effects: Vec<String> = hud
+        .recent
+        .iter()
+        .map(|(label, _)| label.to_string())
+        .collect();
+
+    if state.score.combo > 1 {
+        effects.push(format!("combo  x{}", state.score.multiplier()));
+    }
+    for (actor, velocity) in query_actor.iter() {
+        if actor.braking {
+            effects.push("braking".to_string());
+        }
+        if -velocity.linear.y > DAMAGE_SPEED {
+            effects.push("burning".to_string());
+        }
+    }
+    if indoctrination.map_or(false, |settings| settings.enabled) {
+        effects.push("indoctrinated".to_string());
+    }
+
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = effects.join("\n");
+    }
+}
+
+fn sys_update_danger(
+    time: Res<Time>,
+    query_actor: Query<&Velocity, With<Actor>>,
+    mut query_text: Query<&mut Text, With<DangerText>>,
+) {
+    let speed = match query_actor.iter().next() {
+        Some(velocity) => -velocity.linear.y,
+        None => return,
+    };
+
+    for mut text in query_text.iter_mut() {
+        let section = &mut text.sections[0];
+
+        if speed < DAMAGE_SPEED * DANGER_SHARE {
+            section.value.clear();
+            continue;
+        }
+
+        section.value = match speed > DAMAGE_SPEED {
+            true => "BURNING".to_string(),
+            false => "DANGER".to_string(),
+        };
+
+        // Blinks faster the closer the player is to the damage speed
+        let closeness = (speed / DAMAGE_SPEED).min(1.0);
+        let blink = (time.seconds_since_startup() as f32 * (4.0 + 8.0 * closeness)).sin();
+        section.style.color.set_a(0.5 + 0.5 * blink);
+    }
+}
+
+// Plugins
+
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HudState>()
+            .add_system_set(SystemSet::on_enter(AppState::FallingGame).with_system(sys_draw_hud))
+            .add_system_set(
+                SystemSet::on_update(AppState::FallingGame)
+                    .with_system(sys_update_speedometer)
+                    .with_system(sys_update_health)
+                    .with_system(sys_update_progress)
+                    .with_system(sys_update_effects)
+                    .with_system(sys_update_danger),
+            );
+    }
+}
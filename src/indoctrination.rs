@@ -2,6 +2,9 @@ use bevy::{core::FixedTimestep, prelude::*};
 
 use rand::Rng;
 
+use crate::app_states::*;
+use crate::state_scoped::*;
+
 // Components
 
 #[derive(Component, Default)]
@@ -32,10 +35,11 @@ pub(crate) fn sys_ui_apply_fixed_z(
 pub(crate) fn sys_show_25_frame(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    settings: ResMut<IndoctrinationSettings>,
+    settings: Option<Res<IndoctrinationSettings>>,
     query: Query<Entity, With<IndoctrinationComponent>>,
 ) {
-    if !settings.enabled {
+    // Only present while a falling run is in progress
+    if !settings.map_or(false, |settings| settings.enabled) {
         return;
     }
 
@@ -103,6 +107,7 @@ pub(crate) fn sys_show_25_frame(
             })
             .insert(UiFixedZ { z: 101. })
             .insert(IndoctrinationComponent)
+            .insert(StateScoped(AppState::FallingGame))
             .with_children(|parent| {
                 parent
                     .spawn_bundle(TextBundle {
@@ -153,6 +158,7 @@ pub(crate) fn sys_show_25_frame(
             })
             .insert(UiFixedZ { z: 101. })
             .insert(IndoctrinationComponent)
+            .insert(StateScoped(AppState::FallingGame))
             .with_children(|parent| {
                 parent
                     .spawn_bundle(ImageBundle {
@@ -0,0 +1,265 @@
+use bevy::{
+    core_pipeline::Opaque3d,
+    ecs::system::{lifetimeless::*, SystemParamItem},
+    pbr::{MeshPipeline, MeshPipelineKey, MeshUniform, SetMeshBindGroup, SetMeshViewBindGroup},
+    prelude::*,
+    render::{
+        mesh::{GpuBufferInfo, MeshVertexBufferLayout},
+        render_asset::RenderAssets,
+        render_component::{ExtractComponent, ExtractComponentPlugin},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase,
+            SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::{
+            Buffer, BufferInitDescriptor, BufferUsages, PipelineCache, RenderPipelineDescriptor,
+            SpecializedMeshPipeline, SpecializedMeshPipelineError, SpecializedMeshPipelines,
+            VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
+        },
+        renderer::RenderDevice,
+        view::ExtractedView,
+        RenderApp, RenderStage,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::shader_reload::INSTANCING_SHADER_HANDLE;
+
+// Components
+
+/// Draws the entity's mesh once per instance in a single draw call. Instances are in the
+/// entity's local space, add `NoFrustumCulling` since the mesh bounds don't cover them.
+#[derive(Component, Default, Clone)]
+pub struct MeshInstances(pub Vec<MeshInstance>);
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct MeshInstance {
+    pub translation: [f32; 3],
+    pub scale: f32,
+    pub rotation: [f32; 4],
+    pub color: [f32; 4],
+}
+
+impl MeshInstance {
+    pub fn new(translation: Vec3, rotation: Quat, scale: f32, color: Color) -> Self {
+        Self {
+            translation: translation.to_array(),
+            scale,
+            rotation: rotation.to_array(),
+            color: color.as_linear_rgba_f32(),
+        }
+    }
+}
+
+impl ExtractComponent for MeshInstances {
+    type Query = &'static MeshInstances;
+    type Filter = ();
+
+    fn extract_component(item: bevy::ecs::query::QueryItem<Self::Query>) -> Self {
+        item.clone()
+    }
+}
+
+#[derive(Component)]
+struct InstanceBuffer {
+    buffer: Buffer,
+    length: usize,
+}
+
+// Pipeline
+
+struct InstancingPipeline {
+    mesh_pipeline: MeshPipeline,
+}
+
+impl FromWorld for InstancingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        InstancingPipeline {
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for InstancingPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+        descriptor.vertex.shader = INSTANCING_SHADER_HANDLE.typed();
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<MeshInstance>() as u64,
+            step_mode: VertexStepMode::Instance,
+            // Locations 0 to 2 are the mesh position, normal and uv
+            attributes: vec![
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 3,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 16,
+                    shader_location: 4,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 32,
+                    shader_location: 5,
+                },
+            ],
+        });
+        descriptor.fragment.as_mut().unwrap().shader = INSTANCING_SHADER_HANDLE.typed();
+        descriptor.layout = Some(vec![
+            self.mesh_pipeline.view_layout.clone(),
+            self.mesh_pipeline.mesh_layout.clone(),
+        ]);
+
+        Ok(descriptor)
+    }
+}
+
+type DrawInstanced = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    DrawMeshInstanced,
+);
+
+struct DrawMeshInstanced;
+impl EntityRenderCommand for DrawMeshInstanced {
+    type Param = (
+        SRes<RenderAssets<Mesh>>,
+        SQuery<Read<Handle<Mesh>>>,
+        SQuery<Read<InstanceBuffer>>,
+    );
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (meshes, mesh_query, instance_buffer_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (mesh_handle, instance_buffer) =
+            match (mesh_query.get(item), instance_buffer_query.get_inner(item)) {
+                (Ok(mesh_handle), Ok(instance_buffer)) => (mesh_handle, instance_buffer),
+                _ => return RenderCommandResult::Failure,
+            };
+
+        let gpu_mesh = match meshes.into_inner().get(mesh_handle) {
+            Some(gpu_mesh) => gpu_mesh,
+            None => return RenderCommandResult::Failure,
+        };
+
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, instance_buffer.buffer.slice(..));
+
+        let instances = 0..instance_buffer.length as u32;
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, instances);
+            }
+            GpuBufferInfo::NonIndexed { vertex_count } => {
+                pass.draw(0..*vertex_count, instances);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
+
+// Systems
+
+fn prepare_instance_buffers(
+    mut commands: Commands,
+    query: Query<(Entity, &MeshInstances)>,
+    render_device: Res<RenderDevice>,
+) {
+    for (entity, instances) in query.iter() {
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("Mesh Instance Buffer"),
+            contents: bytemuck::cast_slice(instances.0.as_slice()),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        });
+        commands.entity(entity).insert(InstanceBuffer {
+            buffer,
+            length: instances.0.len(),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_instanced_meshes(
+    opaque_3d_draw_functions: Res<DrawFunctions<Opaque3d>>,
+    instancing_pipeline: Res<InstancingPipeline>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<InstancingPipeline>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    meshes: Res<RenderAssets<Mesh>>,
+    query_instanced: Query<(Entity, &MeshUniform, &Handle<Mesh>, &MeshInstances)>,
+    mut views: Query<(&ExtractedView, &mut RenderPhase<Opaque3d>)>,
+) {
+    let draw_instanced = opaque_3d_draw_functions
+        .read()
+        .get_id::<DrawInstanced>()
+        .unwrap();
+
+    let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples);
+
+    for (view, mut opaque_phase) in views.iter_mut() {
+        let view_row_2 = view.transform.compute_matrix().row(2);
+
+        for (entity, mesh_uniform, mesh_handle, instances) in query_instanced.iter() {
+            if instances.0.is_empty() {
+                continue;
+            }
+
+            if let Some(mesh) = meshes.get(mesh_handle) {
+                let key =
+                    msaa_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+                let pipeline = match pipelines.specialize(
+                    &mut pipeline_cache,
+                    &instancing_pipeline,
+                    key,
+                    &mesh.layout,
+                ) {
+                    Ok(pipeline) => pipeline,
+                    Err(error) => {
+                        error!("{}", error);
+                        continue;
+                    }
+                };
+
+                opaque_phase.add(Opaque3d {
+                    entity,
+                    pipeline,
+                    draw_function: draw_instanced,
+                    distance: view_row_2.dot(mesh_uniform.transform.col(3)),
+                });
+            }
+        }
+    }
+}
+
+// Plugins
+
+pub struct InstancingPlugin;
+impl Plugin for InstancingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(ExtractComponentPlugin::<MeshInstances>::default());
+        app.sub_app_mut(RenderApp)
+            .add_render_command::<Opaque3d, DrawInstanced>()
+            .init_resource::<InstancingPipeline>()
+            .init_resource::<SpecializedMeshPipelines<InstancingPipeline>>()
+            .add_system_to_stage(RenderStage::Prepare, prepare_instance_buffers)
+            .add_system_to_stage(RenderStage::Queue, queue_instanced_meshes);
+    }
+}
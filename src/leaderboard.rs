@@ -0,0 +1,90 @@
+use crate::endless::*;
+use crate::saves::*;
+
+const LEADERBOARD_SAVE: &str = "leaderboard.txt";
+const ENDLESS_LEADERBOARD_SAVE: &str = "endless_leaderboard.txt";
+const LEADERBOARD_SIZE: usize = 10;
+
+// Leaderboard
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LeaderboardEntry {
+    pub time: u32,
+    pub assisted: bool,
+    pub depth: u32,
+}
+
+impl LeaderboardEntry {
+    /// One line of a table of runs of `mode`.
+    pub fn describe(&self, mode: RunMode) -> String {
+        match mode {
+            RunMode::Endless => format!("{}  deep  in  {}  seconds", self.depth, self.time),
+            _ if self.assisted => format!("{}  seconds  assisted", self.time),
+            _ => format!("{}  seconds", self.time),
+        }
+    }
+}
+
+/// Best runs of one mode, story runs rank by time and endless runs by depth.
+#[derive(Default)]
+pub(crate) struct Leaderboard {
+    pub mode: RunMode,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+fn save_name(mode: RunMode) -> &'static str {
+    match mode {
+        // A time attack is the story with splits, runs started late are marked assisted
+        RunMode::Story | RunMode::TimeAttack => LEADERBOARD_SAVE,
+        RunMode::Endless => ENDLESS_LEADERBOARD_SAVE,
+    }
+}
+
+impl Leaderboard {
+    pub fn load(mode: RunMode) -> Self {
+        let contents = read_save(save_name(mode)).unwrap_or_default();
+
+        let entries = contents
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                Some(LeaderboardEntry {
+                    time: parts.next()?.parse().ok()?,
+                    assisted: parts.next() == Some("assisted"),
+                    // Older saves have no depth
+                    depth: parts
+                        .next()
+                        .and_then(|depth| depth.parse().ok())
+                        .unwrap_or(0),
+                })
+            })
+            .collect();
+
+        Self { mode, entries }
+    }
+
+    pub fn save(&self) {
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let marker = if entry.assisted { "assisted" } else { "clean" };
+                format!("{} {} {}\n", entry.time, marker, entry.depth)
+            })
+            .collect();
+
+        write_save(save_name(self.mode), &contents);
+    }
+
+    /// Records a finished run, keeping the best runs first.
+    pub fn record(&mut self, entry: LeaderboardEntry) {
+        self.entries.push(entry);
+        match self.mode {
+            RunMode::Story | RunMode::TimeAttack => self.entries.sort_by_key(|entry| entry.time),
+            RunMode::Endless => self
+                .entries
+                .sort_by_key(|entry| std::cmp::Reverse(entry.depth)),
+        }
+        self.entries.truncate(LEADERBOARD_SIZE);
+    }
+}
@@ -0,0 +1,301 @@
+use bevy::{asset::LoadState, prelude::*};
+
+use crate::app_states::*;
+use crate::bloodfield::*;
+use crate::main_menu::*;
+use crate::state_scoped::*;
+use crate::widgets::*;
+
+// Components
+
+#[derive(Component, Default)]
+pub struct ProgressBar;
+
+#[derive(Component, Default)]
+pub struct ProgressText;
+
+// Resources
+
+pub(crate) struct LoadingSettings {
+    pub next_state: AppState,
+}
+
+#[derive(Default)]
+pub(crate) struct LoadingAssets {
+    /// Assets the loading screen is waiting on.
+    handles: Vec<HandleUntyped>,
+    /// Assets of the state that was loaded last, held so they stay loaded while it runs.
+    loaded: Vec<HandleUntyped>,
+}
+
+// Assets
+
+fn assets_for_state(state: &AppState) -> &'static [&'static str] {
+    match state {
+        AppState::MainMenu => &[
+            "music/biboran.mp3",
+            "music/click.mp3",
+            "music/hover.mp3",
+            "images/abdulovhell.jpg",
+            "fonts/FiraMono-Medium.ttf",
+            FONT,
+            "shaders/bloodfield.wgsl",
+            "bloodfield/menu.bloodfield",
+        ],
+        AppState::FallingGame => &[
+            "music/aaa-1.mp3",
+            "music/box-hit.mp3",
+            "music/click.mp3",
+            "music/hover.mp3",
+            "models/scene.gltf#Mesh0/Primitive0",
+            "images/speedometer.png",
+            FONT,
+            "fonts/AThemeForMurder-3aPG.ttf",
+            "shaders/bloodfield.wgsl",
+            "shaders/fire.wgsl",
+            "shaders/particles.wgsl",
+            "shaders/instancing.wgsl",
+            "particles/pickup.particles",
+            "particles/embers.particles",
+            "particles/wall_sparks.particles",
+            "particles/teleport_vortex.particles",
+            "bloodfield/shaft.bloodfield",
+            "bloodfield/game_over.bloodfield",
+        ],
+        AppState::CutScene => &[
+            "images/story/1.png",
+            "images/story/2.png",
+            "images/story/3.png",
+            "images/story/4.png",
+            "images/story/5.png",
+            FONT,
+        ],
+        _ => &[],
+    }
+}
+
+/// Switches to the loading screen, which moves on to `next_state` once its assets are ready.
+pub(crate) fn load_state(
+    app_state: &mut State<AppState>,
+    settings: &mut LoadingSettings,
+    next_state: AppState,
+) {
+    settings.next_state = next_state;
+    app_state.set(AppState::Loading).unwrap();
+}
+
+// Systems
+
+fn sys_queue_assets(
+    asset_server: Res<AssetServer>,
+    settings: Res<LoadingSettings>,
+    mut loading_assets: ResMut<LoadingAssets>,
+) {
+    for path in assets_for_state(&settings.next_state) {
+        loading_assets
+            .handles
+            .push(asset_server.load_untyped(*path));
+    }
+}
+
+fn sys_spawn_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut bmaterials: ResMut<Assets<BloodfieldMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    let camera = spawn_menu_camera(&mut commands);
+    commands
+        .entity(camera)
+        .insert(StateScoped(AppState::Loading));
+
+    let backdrop = spawn_bloodfield_backdrop(
+        &mut commands,
+        &mut meshes,
+        &mut bmaterials,
+        asset_server.load("bloodfield/menu.bloodfield"),
+    );
+    commands
+        .entity(backdrop)
+        .insert(StateScoped(AppState::Loading));
+
+    let pentagram = spawn_pentagram(&mut commands, &mut meshes, &mut materials);
+    commands
+        .entity(pentagram)
+        .insert(StateScoped(AppState::Loading));
+
+    commands
+        .spawn_bundle(PointLightBundle {
+            point_light: PointLight {
+                intensity: 200.0,
+                shadows_enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(StateScoped(AppState::Loading));
+
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(StateScoped(AppState::Loading));
+
+    let progress_text = Text::with_section(
+        "loading",
+        TextStyle {
+            font_size: 20.0,
+            font: asset_server.load(FONT),
+            color: Color::WHITE,
+        },
+        TextAlignment {
+            horizontal: HorizontalAlign::Center,
+            ..Default::default()
+        },
+    );
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::FlexStart,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(StateScoped(AppState::Loading))
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: progress_text,
+                    style: Style {
+                        margin: Rect {
+                            top: Val::Percent(75.0),
+                            bottom: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(ProgressText);
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(40.0), Val::Px(12.0)),
+                        ..Default::default()
+                    },
+                    color: Color::rgba(0.1, 0.01, 0.01, 0.8).into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                ..Default::default()
+                            },
+                            color: Color::rgb(0.8, 0.1, 0.1).into(),
+                            ..Default::default()
+                        })
+                        .insert(ProgressBar);
+                });
+        });
+}
+
+fn sys_update_progress(
+    asset_server: Res<AssetServer>,
+    settings: Res<LoadingSettings>,
+    loading_assets: Res<LoadingAssets>,
+    mut app_state: ResMut<State<AppState>>,
+    mut set: ParamSet<(
+        Query<&mut Style, With<ProgressBar>>,
+        Query<&mut Text, With<ProgressText>>,
+    )>,
+) {
+    let total = loading_assets.handles.len();
+    let mut settled = 0;
+    let mut failed = 0;
+
+    for handle in loading_assets.handles.iter() {
+        match asset_server.get_load_state(handle) {
+            LoadState::Loaded => settled += 1,
+            LoadState::Failed => {
+                settled += 1;
+                failed += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let progress = if total == 0 {
+        1.0
+    } else {
+        settled as f32 / total as f32
+    };
+
+    for mut style in set.p0().iter_mut() {
+        style.size.width = Val::Percent(progress * 100.0);
+    }
+
+    for mut text in set.p1().iter_mut() {
+        text.sections[0].value = match failed {
+            0 => format!("loading   {}", (progress * 100.0) as i32),
+            _ => format!(
+                "loading   {}   {}  missing",
+                (progress * 100.0) as i32,
+                failed
+            ),
+        };
+    }
+
+    if settled == total {
+        app_state.set(settings.next_state.clone()).unwrap();
+    }
+}
+
+fn sys_finish_loading(asset_server: Res<AssetServer>, mut loading_assets: ResMut<LoadingAssets>) {
+    let mut handles = std::mem::take(&mut loading_assets.handles);
+
+    handles.retain(|handle| {
+        if asset_server.get_load_state(handle) == LoadState::Failed {
+            warn!(
+                "asset {:?} failed to load",
+                asset_server.get_handle_path(handle)
+            );
+            return false;
+        }
+
+        true
+    });
+
+    // Only the state being entered keeps its assets, the one before lets go of them
+    loading_assets.loaded = handles;
+}
+
+// Plugins
+
+pub struct LoadingPlugin;
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LoadingSettings {
+            next_state: AppState::MainMenu,
+        })
+        .init_resource::<LoadingAssets>()
+        .add_system_set(
+            SystemSet::on_enter(AppState::Loading)
+                .with_system(sys_queue_assets)
+                .with_system(sys_spawn_scene),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Loading)
+                .with_system(sys_rotate_cube)
+                .with_system(draw_random_lines)
+                .with_system(sys_update_progress),
+        )
+        .add_system_set(SystemSet::on_exit(AppState::Loading).with_system(sys_finish_loading));
+    }
+}
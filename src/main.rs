@@ -1,37 +1,74 @@
-use bevy::prelude::*;
+use bevy::{asset::AssetServerSettings, prelude::*};
 use bevy_inspector_egui::WorldInspectorPlugin;
 use bevy_kira_audio::AudioPlugin;
 use bevy_prototype_debug_lines::DebugLinesPlugin;
 
+mod achievements;
 mod app_states;
+mod audio;
 mod bloodfield;
+mod camera;
+mod checkpoint;
 mod cutscene;
+mod death;
+mod endless;
 mod falling;
+mod fire;
 mod game_end;
 mod game_over;
+mod gameplay_events;
+mod hud;
 mod indoctrination;
+mod instancing;
+mod leaderboard;
+mod loading;
 mod main_menu;
+mod particles;
+mod post_process;
+mod saves;
+mod score;
+mod settings;
+mod shader_reload;
+mod shaft;
+mod spatial_audio;
+mod state_scoped;
+mod time_attack;
+mod widgets;
 
 fn main() {
     let mut app = App::new();
 
     app.insert_resource(Msaa { samples: 4 })
+        // Pick up asset edits, shaders in particular, while developing
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: cfg!(debug_assertions),
+            ..default()
+        })
         // External plugins
         .add_plugins(DefaultPlugins)
+        .add_plugin(shader_reload::ShaderReloadPlugin)
         .add_plugin(AudioPlugin)
+        .add_plugin(settings::SettingsPlugin)
+        .add_plugin(audio::AudioManagerPlugin)
+        .add_plugin(spatial_audio::SpatialAudioPlugin)
+        .add_plugin(state_scoped::StateScopedPlugin)
+        .add_plugin(particles::ParticlesPlugin)
+        .add_plugin(instancing::InstancingPlugin)
+        .add_plugin(achievements::AchievementsPlugin)
+        .add_plugin(widgets::WidgetsPlugin)
         // .add_plugin(WorldInspectorPlugin::new())
         .add_plugin(DebugLinesPlugin::with_depth_test(true))
+        // Loading screen
+        .add_plugin(loading::LoadingPlugin)
         // Main menu
         .add_plugin(main_menu::MainMenuPlugin)
         // Screens
         .add_plugin(game_over::GameOverScreenPlugin)
-        // Screens
-        .insert_resource(game_end::GameStats { time: 0 })
         .add_plugin(game_end::GameEndPlugin)
         // Falling Game
         .add_plugin(falling::FallingMinigamePlugin)
         // States
-        .add_state(app_states::AppState::MainMenu);
+        .add_state(app_states::AppState::Loading);
 
     app.run();
 }
@@ -1,24 +1,67 @@
+use bevy::app::AppExit;
 use bevy::prelude::*;
-use bevy_kira_audio::Audio;
 use bevy_prototype_debug_lines::DebugLines;
 
+use crate::achievements::*;
 use crate::app_states::*;
+use crate::audio::*;
 use crate::bloodfield::*;
+use crate::checkpoint::*;
 use crate::cutscene::CutscenePlugin;
-use crate::cutscene::CutsceneSettings;
+use crate::endless::*;
+use crate::leaderboard::*;
+use crate::loading::*;
+use crate::settings::*;
+use crate::state_scoped::*;
+use crate::time_attack::*;
+use crate::widgets::*;
 use rand::Rng;
 
+const CREDITS: &str = "artwork  by  biboran  artists\na  theme  for  a  murder  font  by  Livin  Hell\nARCADE  font  by  anonymous\nDichotomy  and  Absolom  tracks  by  Electric  Senses\nMade  on  Bevy  with  Rust";
+
 // Components
 
 #[derive(Component, Default)]
 pub struct Cube;
 
-#[derive(Component, Default)]
-pub struct MainMenuComponent;
-
 #[derive(Component, Default)]
 pub struct Pentagram;
 
+/// A page of the menu sliding in next to the buttons, or out when `closing`.
+#[derive(Component)]
+pub struct SubPage {
+    page: MenuPage,
+    /// 0 while out of view, 1 once fully shown.
+    shown: f32,
+    closing: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MenuPage {
+    Leaderboard,
+    Achievements,
+    Settings,
+    Credits,
+}
+
+impl MenuPage {
+    pub const ALL: [MenuPage; 4] = [
+        MenuPage::Leaderboard,
+        MenuPage::Achievements,
+        MenuPage::Settings,
+        MenuPage::Credits,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            MenuPage::Leaderboard => "LEADERBOARD",
+            MenuPage::Achievements => "ACHIEVEMENTS",
+            MenuPage::Settings => "SETTINGS",
+            MenuPage::Credits => "CREDITS",
+        }
+    }
+}
+
 // Bundles
 
 #[derive(Bundle, Default)]
@@ -26,12 +69,25 @@ struct CubeBundle {
     #[bundle]
     pbr_bundle: PbrBundle,
     cube: Cube,
-    marker: MainMenuComponent,
 }
 
+// GAMEPLAY VARIABLES
+
+const PAGE_SLIDE_SECONDS: f32 = 0.35;
+const PAGE_SHOWN_RIGHT: f32 = 5.0;
+const PAGE_HIDDEN_RIGHT: f32 = -60.0;
+const LEADERBOARD_ROWS: usize = 5;
+
 // Systems
 
 fn sys_setup_camera(mut commands: Commands) {
+    let camera = spawn_menu_camera(&mut commands);
+    commands
+        .entity(camera)
+        .insert(StateScoped(AppState::MainMenu));
+}
+
+pub(crate) fn spawn_menu_camera(commands: &mut Commands) -> Entity {
     let mut camera_transform = Transform::from_matrix(Mat4::from_rotation_translation(
         Quat::from_xyzw(-0.3, -0.5, -0.3, 0.5).normalize(),
         Vec3::new(-10.0, 18.0, 0.0),
@@ -45,67 +101,42 @@ fn sys_setup_camera(mut commands: Commands) {
             transform: camera_transform,
             ..Default::default()
         })
-        .insert(MainMenuComponent);
+        .id()
 }
 
-pub fn sys_spawn_circle_of_cubes(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut bmaterials: ResMut<Assets<BloodfieldMaterial>>,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>,
-) {
-    audio.play_looped(asset_server.load("music/biboran.mp3"));
-
-    let texture_handle = asset_server.load("images/abdulovhell.jpg");
-
-    let red_material_handle = materials.add(StandardMaterial {
-        base_color: Color::rgba(1.0, 0.6, 0.6, 0.5),
-        base_color_texture: Some(texture_handle.clone()),
-        alpha_mode: AlphaMode::Blend,
-        unlit: true,
-        ..default()
-    });
-
+pub(crate) fn spawn_bloodfield_backdrop(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    bmaterials: &mut Assets<BloodfieldMaterial>,
+    preset: Handle<BloodfieldPreset>,
+) -> Entity {
     let mesh = meshes.add(Mesh::from(shape::Plane { size: 1. }));
 
-    let mut image_transform =
-        Transform::from_translation(Vec3::new(-5.5, 7.1, 0.0)).with_scale(Vec3::new(5.0, 0.0, 7.3));
-
-    image_transform.rotate(Quat::from_rotation_y(std::f32::consts::PI / 2.0));
-
-    let bloodfield_material = bmaterials.add(BloodfieldMaterial {
-        time: 0.0,
-        seed: rand::thread_rng().gen::<i16>() as f32,
-    });
-
-    let bundle = MaterialMeshBundle {
-        mesh: mesh.clone(),
-        material: red_material_handle,
-        transform: image_transform,
-        ..default()
-    };
-
-    commands.spawn_bundle(bundle).insert(MainMenuComponent);
+    let bloodfield_material = bmaterials.add(BloodfieldMaterial::from_preset(
+        preset,
+        rand::thread_rng().gen::<i16>() as f32,
+    ));
 
-    // Spawn background shader mesh
     let mut image_transform = Transform::from_translation(Vec3::new(-5.5, 0.0, 0.0))
         .with_scale(Vec3::new(25.0, 0.0, 25.0));
 
     image_transform.rotate(Quat::from_rotation_y(std::f32::consts::PI / 2.0));
 
     let bundle = MaterialMeshBundle {
-        mesh: mesh.clone(),
+        mesh,
         material: bloodfield_material,
-        // material: red_material_handle,
         transform: image_transform,
         ..default()
     };
 
-    commands.spawn_bundle(bundle).insert(MainMenuComponent);
+    commands.spawn_bundle(bundle).id()
+}
 
-    // Spawn Circle of Cubes
+pub(crate) fn spawn_pentagram(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> Entity {
     commands
         .spawn_bundle(PbrBundle { ..default() })
         .with_children(|parent| {
@@ -143,7 +174,64 @@ pub fn sys_spawn_circle_of_cubes(
             }
         })
         .insert(Pentagram)
-        .insert(MainMenuComponent);
+        .id()
+}
+
+pub fn sys_spawn_circle_of_cubes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut bmaterials: ResMut<Assets<BloodfieldMaterial>>,
+    asset_server: Res<AssetServer>,
+    run_options: Res<RunOptions>,
+    mut music: EventWriter<PlayMusic>,
+) {
+    music.send(PlayMusic::Track("music/biboran.mp3"));
+
+    let texture_handle = asset_server.load("images/abdulovhell.jpg");
+
+    let red_material_handle = materials.add(StandardMaterial {
+        base_color: Color::rgba(1.0, 0.6, 0.6, 0.5),
+        base_color_texture: Some(texture_handle.clone()),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
+    let mesh = meshes.add(Mesh::from(shape::Plane { size: 1. }));
+
+    let mut image_transform =
+        Transform::from_translation(Vec3::new(-5.5, 7.1, 0.0)).with_scale(Vec3::new(5.0, 0.0, 7.3));
+
+    image_transform.rotate(Quat::from_rotation_y(std::f32::consts::PI / 2.0));
+
+    let bundle = MaterialMeshBundle {
+        mesh: mesh.clone(),
+        material: red_material_handle,
+        transform: image_transform,
+        ..default()
+    };
+
+    commands
+        .spawn_bundle(bundle)
+        .insert(StateScoped(AppState::MainMenu));
+
+    // Spawn background shader mesh
+    let backdrop = spawn_bloodfield_backdrop(
+        &mut commands,
+        &mut meshes,
+        &mut bmaterials,
+        asset_server.load("bloodfield/menu.bloodfield"),
+    );
+    commands
+        .entity(backdrop)
+        .insert(StateScoped(AppState::MainMenu));
+
+    // Spawn Circle of Cubes
+    let pentagram = spawn_pentagram(&mut commands, &mut meshes, &mut materials);
+    commands
+        .entity(pentagram)
+        .insert(StateScoped(AppState::MainMenu));
 
     // Spawn light source
     commands
@@ -156,12 +244,12 @@ pub fn sys_spawn_circle_of_cubes(
             transform: Transform::from_xyz(0.0, 0.0, 0.0),
             ..Default::default()
         })
-        .insert(MainMenuComponent);
+        .insert(StateScoped(AppState::MainMenu));
 
     // Draw Title
     commands
         .spawn_bundle(UiCameraBundle::default())
-        .insert(MainMenuComponent);
+        .insert(StateScoped(AppState::MainMenu));
 
     let text = Text::with_section(
         "CVLT OV BIBΩRAN",
@@ -183,80 +271,300 @@ pub fn sys_spawn_circle_of_cubes(
                 size: Size::new(Val::Percent(33.0), Val::Percent(100.0)),
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
-                flex_direction: FlexDirection::Column,
+                flex_direction: FlexDirection::ColumnReverse,
                 ..Default::default()
             },
-            color: NORMAL_BUTTON.into(),
-            visibility: Visibility { is_visible: false },
+            color: Color::NONE.into(),
             ..Default::default()
         })
         .with_children(|parent| {
-            parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(170.0), Val::Px(65.0)),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        position: Rect {
-                            top: Val::Px(50.0),
-                            ..Default::default()
-                        },
+            parent.spawn_bundle(TextBundle {
+                text,
+                style: Style {
+                    margin: Rect {
+                        bottom: Val::Px(20.0),
                         ..Default::default()
                     },
-                    color: Color::rgb(0.6, 0.1, 0.1).into(),
                     ..Default::default()
-                })
-                .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "CONFESS",
-                            TextStyle {
-                                font: asset_server.load("fonts/ARCADECLASSIC.TTF"),
-                                font_size: 20.0,
-                                color: Color::WHITE.into(),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    });
-                });
-
-            parent.spawn_bundle(TextBundle {
-                text,
+                },
                 ..Default::default()
             });
+
+            for (label, mode) in [
+                ("CONFESS", RunMode::Story),
+                ("ENDLESS", RunMode::Endless),
+                ("TIME  ATTACK", RunMode::TimeAttack),
+            ] {
+                spawn_button(
+                    parent,
+                    &asset_server,
+                    label,
+                    MenuAction::Play(mode),
+                    WidgetSize::Large,
+                );
+            }
+
+            spawn_button(
+                parent,
+                &asset_server,
+                start_cycle_label(run_options.start_cycle),
+                MenuAction::NextStartCycle,
+                WidgetSize::Small,
+            );
+
+            for page in MenuPage::ALL {
+                spawn_button(
+                    parent,
+                    &asset_server,
+                    page.title(),
+                    MenuAction::OpenPage(page),
+                    WidgetSize::Small,
+                );
+            }
+
+            spawn_button(
+                parent,
+                &asset_server,
+                "QUIT",
+                MenuAction::Quit,
+                WidgetSize::Small,
+            );
         })
-        .insert(MainMenuComponent);
+        .insert(StateScoped(AppState::MainMenu));
+}
+
+fn start_cycle_label(start_cycle: u8) -> String {
+    format!("FROM  CYCLE  {}", start_cycle + 1)
 }
 
-const NORMAL_BUTTON: Color = Color::rgb(0.65, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.75, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(1.0, 0.35, 0.25);
+fn spawn_leaderboard(parent: &mut ChildBuilder, asset_server: &AssetServer) {
+    for (title, mode) in [("STORY", RunMode::Story), ("ENDLESS", RunMode::Endless)] {
+        let leaderboard = Leaderboard::load(mode);
 
-fn sys_button_new_game(
-    mut interaction_query: Query<
-        (&Interaction, &mut UiColor, &Children),
-        (Changed<Interaction>, With<Button>),
-    >,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>,
+        parent.spawn_bundle(label_bundle(asset_server, title, 25.0, Color::WHITE));
+
+        if leaderboard.entries.is_empty() {
+            let mut empty = label_bundle(asset_server, "no  runs  yet", 14.0, Color::WHITE);
+            empty.style.margin.bottom = Val::Px(12.0);
+            parent.spawn_bundle(empty);
+            continue;
+        }
+
+        let rows = leaderboard
+            .entries
+            .iter()
+            .take(LEADERBOARD_ROWS)
+            .enumerate()
+            .map(|(i, entry)| format!("{}   {}\n", i + 1, entry.describe(mode)))
+            .collect::<String>();
+
+        let mut table = label_bundle(asset_server, rows, 14.0, Color::WHITE);
+        table.style.margin.bottom = Val::Px(12.0);
+        parent.spawn_bundle(table);
+    }
+}
+
+fn spawn_settings(parent: &mut ChildBuilder, asset_server: &AssetServer, settings: &Settings) {
+    for (label, action, value) in [
+        ("MUSIC", MenuAction::MusicVolume, settings.music_volume),
+        ("SOUND", MenuAction::SoundVolume, settings.sound_volume),
+        ("VOICE", MenuAction::VoiceVolume, settings.voice_volume),
+    ] {
+        spawn_slider(
+            parent,
+            asset_server,
+            label,
+            Slider {
+                action,
+                value,
+                min: 0.0,
+                max: 1.0,
+                step: 0.1,
+            },
+        );
+    }
+
+    spawn_toggle(
+        parent,
+        asset_server,
+        "SCREEN  SHAKE",
+        MenuAction::ScreenShake,
+        settings.screen_shake,
+    );
+    spawn_toggle(
+        parent,
+        asset_server,
+        "FULLSCREEN",
+        MenuAction::Fullscreen,
+        settings.fullscreen,
+    );
+}
+
+fn spawn_page(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    achievements: &Achievements,
+    settings: &Settings,
+    page: MenuPage,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(15.0),
+                    right: Val::Percent(PAGE_HIDDEN_RIGHT),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(SubPage {
+            page,
+            shown: 0.0,
+            closing: false,
+        })
+        .insert(StateScoped(AppState::MainMenu))
+        .with_children(|parent| match page {
+            MenuPage::Achievements => {
+                spawn_list(
+                    parent,
+                    asset_server,
+                    Achievement::ALL.into_iter().map(|achievement| ListItem {
+                        title: achievement.title().to_string(),
+                        detail: achievement.description().to_string(),
+                        dimmed: !achievements.is_unlocked(achievement),
+                    }),
+                );
+            }
+            _ => {
+                parent
+                    .spawn_bundle(panel_bundle())
+                    .with_children(|parent| match page {
+                        MenuPage::Leaderboard => spawn_leaderboard(parent, asset_server),
+                        MenuPage::Settings => spawn_settings(parent, asset_server, settings),
+                        _ => {
+                            parent.spawn_bundle(label_bundle(
+                                asset_server,
+                                CREDITS,
+                                14.0,
+                                Color::WHITE,
+                            ));
+                        }
+                    });
+            }
+        });
+}
+
+fn sys_menu_actions(
+    mut commands: Commands,
+    mut activated: EventReader<ButtonActivated>,
     mut app_state: ResMut<State<AppState>>,
+    mut loading_settings: ResMut<LoadingSettings>,
+    mut run_options: ResMut<RunOptions>,
+    mut exit: EventWriter<AppExit>,
+    asset_server: Res<AssetServer>,
+    achievements: Res<Achievements>,
+    settings: Res<Settings>,
+    query_buttons: Query<(&MenuButton, &Children)>,
+    mut query_pages: Query<&mut SubPage>,
+    mut query_text: Query<&mut Text>,
 ) {
-    for (interaction, mut color, _) in interaction_query.iter_mut() {
-        match *interaction {
-            Interaction::Clicked => {
-                audio.play(asset_server.load("music/click.mp3"));
-                *color = PRESSED_BUTTON.into();
-                app_state.set(AppState::FallingGame).unwrap();
+    for ButtonActivated(action) in activated.iter() {
+        match *action {
+            MenuAction::Play(mode) => {
+                run_options.mode = mode;
+                load_state(&mut app_state, &mut loading_settings, AppState::FallingGame);
             }
-            Interaction::Hovered => {
-                audio.play(asset_server.load("music/hover.mp3"));
-                *color = HOVERED_BUTTON.into();
+            MenuAction::NextStartCycle => {
+                let unlocked = BestSplits::load().unlocked.min(STORY_CYCLES - 1);
+                run_options.start_cycle = (run_options.start_cycle + 1) % (unlocked + 1);
+
+                for (button, children) in query_buttons.iter() {
+                    if button.action != MenuAction::NextStartCycle {
+                        continue;
+                    }
+                    for child in children.iter() {
+                        if let Ok(mut text) = query_text.get_mut(*child) {
+                            text.sections[0].value = start_cycle_label(run_options.start_cycle);
+                        }
+                    }
+                }
             }
-            Interaction::None => {
-                *color = NORMAL_BUTTON.into();
+            MenuAction::OpenPage(page) => {
+                // The open page slides out, a different one slides in in its place
+                let mut was_open = false;
+                for mut sub_page in query_pages.iter_mut().filter(|p| !p.closing) {
+                    was_open |= sub_page.page == page;
+                    sub_page.closing = true;
+                }
+
+                if !was_open {
+                    spawn_page(&mut commands, &asset_server, &achievements, &settings, page);
+                }
             }
+            MenuAction::Quit => exit.send(AppExit),
+            _ => {}
+        }
+    }
+}
+
+fn sys_settings_changed(
+    mut settings: ResMut<Settings>,
+    mut sliders: EventReader<SliderChanged>,
+    mut toggles: EventReader<ToggleChanged>,
+) {
+    let mut changed = false;
+
+    for event in sliders.iter() {
+        match event.action {
+            MenuAction::MusicVolume => settings.music_volume = event.value,
+            MenuAction::SoundVolume => settings.sound_volume = event.value,
+            MenuAction::VoiceVolume => settings.voice_volume = event.value,
+            _ => continue,
+        }
+        changed = true;
+    }
+
+    for event in toggles.iter() {
+        match event.action {
+            MenuAction::ScreenShake => settings.screen_shake = event.on,
+            MenuAction::Fullscreen => settings.fullscreen = event.on,
+            _ => continue,
         }
+        changed = true;
+    }
+
+    if changed {
+        settings.save();
+    }
+}
+
+fn sys_animate_pages(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query_pages: Query<(Entity, &mut SubPage, &mut Style)>,
+) {
+    let step = time.delta_seconds() / PAGE_SLIDE_SECONDS;
+
+    for (e, mut sub_page, mut style) in query_pages.iter_mut() {
+        sub_page.shown = match sub_page.closing {
+            true => sub_page.shown - step,
+            false => sub_page.shown + step,
+        }
+        .clamp(0.0, 1.0);
+
+        if sub_page.closing && sub_page.shown <= 0.0 {
+            commands.entity(e).despawn_recursive();
+            continue;
+        }
+
+        // Eases out, quick to arrive and slow to settle
+        let eased = 1.0 - (1.0 - sub_page.shown).powi(3);
+        style.position.right =
+            Val::Percent(PAGE_HIDDEN_RIGHT + (PAGE_SHOWN_RIGHT - PAGE_HIDDEN_RIGHT) * eased);
     }
 }
 
@@ -315,19 +623,6 @@ pub fn draw_random_lines(mut lines: ResMut<DebugLines>) {
     }
 }
 
-fn sys_clear_entities(
-    mut commands: Commands,
-    audio: Res<Audio>,
-    // mut app_state: ResMut<State<AppState>>,
-    mut main_menu_components: Query<Entity, With<MainMenuComponent>>,
-) {
-    for e in main_menu_components.iter_mut() {
-        commands.entity(e).despawn_recursive();
-    }
-
-    audio.stop();
-}
-
 // Plugins
 
 pub struct MainMenuPlugin;
@@ -338,15 +633,15 @@ impl Plugin for MainMenuPlugin {
                 SystemSet::on_update(AppState::MainMenu)
                     .with_system(sys_rotate_cube)
                     .with_system(draw_random_lines)
-                    .with_system(sys_button_new_game),
+                    .with_system(sys_menu_actions)
+                    .with_system(sys_settings_changed)
+                    .with_system(sys_animate_pages),
             )
             .add_system_set(
                 SystemSet::on_enter(AppState::MainMenu)
                     .with_system(sys_setup_camera)
                     .with_system(sys_spawn_circle_of_cubes),
             )
-            .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(sys_clear_entities))
-            .add_plugin(CutscenePlugin)
-            .insert_resource(CutsceneSettings { next_stage: 0 });
+            .add_plugin(CutscenePlugin);
     }
 }
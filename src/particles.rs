@@ -0,0 +1,649 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    pbr::{MaterialPipeline, NotShadowCaster},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::{Indices, MeshVertexAttribute, MeshVertexBufferLayout, PrimitiveTopology},
+        render_asset::{PrepareAssetError, RenderAsset},
+        render_resource::{
+            std140::{AsStd140, Std140},
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BlendComponent,
+            BlendFactor, BlendOperation, BlendState, Buffer, BufferBindingType,
+            BufferInitDescriptor, BufferSize, BufferUsages, RenderPipelineDescriptor, ShaderStages,
+            SpecializedMeshPipelineError, VertexFormat,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        view::NoFrustumCulling,
+        RenderApp, RenderStage,
+    },
+    utils::{BoxedFuture, HashMap},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::app_states::*;
+use crate::saves::{field, parse_fields};
+use crate::shader_reload::PARTICLES_SHADER_HANDLE;
+use crate::state_scoped::*;
+
+// Components
+
+/// Draws every particle of `effect` with one mesh, animated entirely in the vertex shader.
+#[derive(Component, Default, Clone)]
+pub struct ParticleEmitter {
+    pub effect: Handle<ParticleEffect>,
+    /// Replaces the effect's start colour, keeping its alpha.
+    pub color: Option<Color>,
+}
+
+/// Emitter that despawns once all of its particles died.
+#[derive(Component)]
+pub struct ParticleBurst {
+    timer: Timer,
+}
+
+// Bundles
+
+#[derive(Bundle, Default)]
+pub struct ParticleEmitterBundle {
+    pub emitter: ParticleEmitter,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub computed_visibility: ComputedVisibility,
+}
+
+impl ParticleEmitterBundle {
+    pub fn new(effect: Handle<ParticleEffect>, transform: Transform) -> Self {
+        Self {
+            emitter: ParticleEmitter {
+                effect,
+                color: None,
+            },
+            transform,
+            ..default()
+        }
+    }
+}
+
+// Events
+
+/// Spawns a one-shot emitter of `effect` at `position`.
+pub(crate) struct SpawnParticles {
+    pub effect: &'static str,
+    pub position: Vec3,
+    pub color: Option<Color>,
+}
+
+impl SpawnParticles {
+    pub fn burst(effect: &'static str, position: Vec3) -> Self {
+        Self {
+            effect,
+            position,
+            color: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+}
+
+// Resources
+
+/// Particle meshes only depend on the effect, so bursts of the same effect share one.
+#[derive(Default)]
+struct ParticleMeshes {
+    meshes: HashMap<Handle<ParticleEffect>, Handle<Mesh>>,
+}
+
+// Effects
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitterShape {
+    Point,
+    Sphere,
+    Ring,
+}
+
+impl std::str::FromStr for EmitterShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "point" => Ok(EmitterShape::Point),
+            "sphere" => Ok(EmitterShape::Sphere),
+            "ring" => Ok(EmitterShape::Ring),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Emitter configuration, loaded from `.particles` files.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "8D4E1B27-3A6C-4F95-B0E2-71C9A5D3F648"]
+pub struct ParticleEffect {
+    pub count: u32,
+    pub lifetime: f32,
+    /// Looping effects re-emit every particle forever, the rest fire once.
+    pub looping: bool,
+    pub shape: EmitterShape,
+    pub radius: f32,
+    /// Zero emits in every direction.
+    pub direction: Vec3,
+    /// 0 keeps particles on `direction`, 1 scatters them over the whole sphere.
+    pub spread: f32,
+    pub speed: f32,
+    pub speed_variance: f32,
+    pub gravity: Vec3,
+    pub drag: f32,
+    /// Radians per second around the emitter's Y axis.
+    pub swirl: f32,
+    pub size: f32,
+    pub start_color: Color,
+    pub end_color: Color,
+}
+
+impl ParticleEffect {
+    fn parse(contents: &str) -> Option<Self> {
+        let fields = parse_fields(contents);
+
+        let floats = |key: &str| -> Option<Vec<f32>> {
+            let value: String = field(&fields, key)?;
+            value
+                .split_whitespace()
+                .map(|channel| channel.parse().ok())
+                .collect()
+        };
+        let vec3 = |key: &str| -> Option<Vec3> {
+            match floats(key)?[..] {
+                [x, y, z] => Some(Vec3::new(x, y, z)),
+                _ => None,
+            }
+        };
+        let color = |key: &str| -> Option<Color> {
+            match floats(key)?[..] {
+                [r, g, b, a] => Some(Color::rgba_linear(r, g, b, a)),
+                _ => None,
+            }
+        };
+
+        Some(Self {
+            count: field(&fields, "count")?,
+            lifetime: field(&fields, "lifetime")?,
+            looping: field(&fields, "looping")?,
+            shape: field(&fields, "shape")?,
+            radius: field(&fields, "radius")?,
+            direction: vec3("direction")?,
+            spread: field(&fields, "spread")?,
+            speed: field(&fields, "speed")?,
+            speed_variance: field(&fields, "speed_variance")?,
+            gravity: vec3("gravity")?,
+            drag: field(&fields, "drag")?,
+            swirl: field(&fields, "swirl")?,
+            size: field(&fields, "size")?,
+            start_color: color("start_color")?,
+            end_color: color("end_color")?,
+        })
+    }
+
+    /// Bakes spawn offsets and velocities into a mesh with one quad per particle.
+    fn build_mesh(&self) -> Mesh {
+        let mut rng = StdRng::seed_from_u64(self.count as u64);
+
+        let quads = self.count as usize;
+        let mut positions = Vec::with_capacity(quads * 4);
+        let mut velocities = Vec::with_capacity(quads * 4);
+        let mut corners = Vec::with_capacity(quads * 4);
+        let mut particles = Vec::with_capacity(quads * 4);
+        let mut indices = Vec::with_capacity(quads * 6);
+
+        for i in 0..self.count {
+            let offset = match self.shape {
+                EmitterShape::Point => Vec3::ZERO,
+                EmitterShape::Sphere => {
+                    random_direction(&mut rng) * self.radius * rng.gen::<f32>().cbrt()
+                }
+                EmitterShape::Ring => {
+                    let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+                    Vec3::new(f32::cos(angle), 0.0, f32::sin(angle)) * self.radius
+                }
+            };
+
+            let direction = match self.direction == Vec3::ZERO {
+                true => random_direction(&mut rng),
+                false => (self.direction.normalize() + random_direction(&mut rng) * self.spread)
+                    .normalize_or_zero(),
+            };
+            let speed = self.speed * (1.0 + self.speed_variance * rng.gen_range(-1.0..1.0));
+            let velocity = direction * speed;
+
+            // Looping particles are spread evenly over the lifetime so the stream is steady
+            let phase = match self.looping {
+                true => i as f32 / self.count as f32,
+                false => 0.0,
+            };
+            let seed: f32 = rng.gen();
+
+            let base = positions.len() as u32;
+            for corner in [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]] {
+                positions.push(offset.to_array());
+                velocities.push(velocity.to_array());
+                corners.push(corner);
+                particles.push([phase, seed]);
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, velocities);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, corners);
+        mesh.insert_attribute(ATTRIBUTE_PARTICLE, particles);
+        mesh.set_indices(Some(Indices::U32(indices)));
+
+        mesh
+    }
+
+    fn material(&self, color: Option<Color>, start_time: f32) -> ParticleMaterial {
+        let start_color = match color {
+            Some(color) => {
+                let mut color = color.as_rgba_linear();
+                color.set_a(self.start_color.a());
+                color
+            }
+            None => self.start_color,
+        };
+
+        ParticleMaterial {
+            start_color,
+            end_color: self.end_color,
+            gravity: self.gravity,
+            start_time,
+            lifetime: self.lifetime,
+            size: self.size,
+            swirl: self.swirl,
+            drag: self.drag,
+            looping: self.looping,
+        }
+    }
+}
+
+fn random_direction(rng: &mut StdRng) -> Vec3 {
+    loop {
+        let v = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        if v.length_squared() > 0.01 && v.length_squared() <= 1.0 {
+            return v.normalize();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ParticleEffectLoader;
+
+impl AssetLoader for ParticleEffectLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let contents = std::str::from_utf8(bytes)?;
+            let effect = ParticleEffect::parse(contents).ok_or_else(|| {
+                anyhow::anyhow!("invalid particle effect {:?}", load_context.path())
+            })?;
+
+            load_context.set_default_asset(LoadedAsset::new(effect));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["particles"]
+    }
+}
+
+// Material
+
+/// Packed per particle as (phase, seed), position holds the spawn offset and normal the velocity.
+const ATTRIBUTE_PARTICLE: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Particle", 0x7061_7274, VertexFormat::Float32x2);
+
+#[derive(Component, Debug, Clone, TypeUuid)]
+#[uuid = "2C7F9A41-5D0E-4B63-8E1A-F4B6C2D8907E"]
+pub struct ParticleMaterial {
+    pub start_color: Color,
+    pub end_color: Color,
+    pub gravity: Vec3,
+    /// Clock time the emitter was built at, the shader ages particles from it with
+    /// `ParticleGlobals`.
+    pub start_time: f32,
+    pub lifetime: f32,
+    pub size: f32,
+    pub swirl: f32,
+    pub drag: f32,
+    pub looping: bool,
+}
+
+#[derive(Clone, AsStd140)]
+struct ParticleUniform {
+    start_color: Vec4,
+    end_color: Vec4,
+    gravity: Vec3,
+    start_time: f32,
+    lifetime: f32,
+    size: f32,
+    swirl: f32,
+    drag: f32,
+    looping: u32,
+}
+
+/// Clock time shared by every particle material, so they animate without being touched.
+#[derive(Clone, AsStd140)]
+struct ParticleGlobalsUniform {
+    time: f32,
+}
+
+/// Render world copy of the clock, see `ParticleMaterial::start_time`.
+struct ParticleTime {
+    seconds: f32,
+}
+
+pub struct ParticleGlobals {
+    buffer: Buffer,
+}
+
+impl FromWorld for ParticleGlobals {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            contents: ParticleGlobalsUniform { time: 0.0 }.as_std140().as_bytes(),
+            label: Some("Particle Globals Buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        Self { buffer }
+    }
+}
+
+#[derive(Clone)]
+pub struct GpuParticleMaterial {
+    bind_group: BindGroup,
+}
+
+impl RenderAsset for ParticleMaterial {
+    type ExtractedAsset = ParticleMaterial;
+    type PreparedAsset = GpuParticleMaterial;
+    type Param = (
+        SRes<RenderDevice>,
+        SRes<MaterialPipeline<Self>>,
+        SRes<ParticleGlobals>,
+    );
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        extracted_asset: Self::ExtractedAsset,
+        (render_device, material_pipeline, globals): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let value = ParticleUniform {
+            start_color: extracted_asset.start_color.as_linear_rgba_f32().into(),
+            end_color: extracted_asset.end_color.as_linear_rgba_f32().into(),
+            gravity: extracted_asset.gravity,
+            start_time: extracted_asset.start_time,
+            lifetime: extracted_asset.lifetime,
+            size: extracted_asset.size,
+            swirl: extracted_asset.swirl,
+            drag: extracted_asset.drag,
+            looping: extracted_asset.looping as u32,
+        };
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            contents: value.as_std140().as_bytes(),
+            label: Some("Particle Settings Buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: globals.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Particle BindGroup"),
+            layout: &material_pipeline.material_layout,
+        });
+
+        Ok(GpuParticleMaterial { bind_group })
+    }
+}
+
+impl Material for ParticleMaterial {
+    fn vertex_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(PARTICLES_SHADER_HANDLE.typed())
+    }
+    fn fragment_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(PARTICLES_SHADER_HANDLE.typed())
+    }
+
+    fn alpha_mode(_render_asset: &<Self as RenderAsset>::PreparedAsset) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn bind_group(render_asset: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &render_asset.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(
+                            ParticleUniform::std140_size_static() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(
+                            ParticleGlobalsUniform::std140_size_static() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Particle BindGroup Layout"),
+        })
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.vertex.buffers = vec![layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            ATTRIBUTE_PARTICLE.at_shader_location(3),
+        ])?];
+
+        // Additive, so overlapping particles glow instead of needing to be sorted
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            for target in fragment.targets.iter_mut() {
+                target.blend = Some(BlendState {
+                    color: additive,
+                    alpha: additive,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Systems
+
+fn sys_spawn_bursts(
+    mut commands: Commands,
+    mut spawn_events: EventReader<SpawnParticles>,
+    asset_server: Res<AssetServer>,
+    app_state: Res<State<AppState>>,
+) {
+    for event in spawn_events.iter() {
+        // Cached bursts share a mesh, a random turn keeps them from looking identical
+        let rotation = Quat::from_rotation_y(rand::random::<f32>() * std::f32::consts::PI * 2.0);
+
+        commands
+            .spawn_bundle(ParticleEmitterBundle {
+                emitter: ParticleEmitter {
+                    effect: asset_server.load(event.effect),
+                    color: event.color,
+                },
+                transform: Transform::from_translation(event.position).with_rotation(rotation),
+                ..default()
+            })
+            .insert(StateScoped(app_state.current().clone()))
+            .insert(Name::new("Particle Burst"));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn sys_build_emitters(
+    mut commands: Commands,
+    time: Res<Time>,
+    effects: Res<Assets<ParticleEffect>>,
+    mut cache: ResMut<ParticleMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ParticleMaterial>>,
+    query_emitter: Query<(Entity, &ParticleEmitter), Without<Handle<ParticleMaterial>>>,
+) {
+    for (entity, emitter) in query_emitter.iter() {
+        let effect = match effects.get(&emitter.effect) {
+            Some(effect) => effect,
+            None => continue,
+        };
+
+        let mesh = cache
+            .meshes
+            .entry(emitter.effect.clone())
+            .or_insert_with(|| meshes.add(effect.build_mesh()))
+            .clone();
+
+        let mut entity = commands.entity(entity);
+        entity
+            .insert(mesh)
+            .insert(
+                materials.add(effect.material(emitter.color, time.seconds_since_startup() as f32)),
+            )
+            .insert(NoFrustumCulling)
+            .insert(NotShadowCaster);
+
+        if !effect.looping {
+            // Particles live up to 1.5 times the effect lifetime, see `particles.wgsl`
+            entity.insert(ParticleBurst {
+                timer: Timer::from_seconds(effect.lifetime * 1.5, false),
+            });
+        }
+    }
+}
+
+/// Edited `.particles` files rebuild every emitter using them.
+fn sys_reload_effects(
+    mut commands: Commands,
+    mut effect_events: EventReader<AssetEvent<ParticleEffect>>,
+    mut cache: ResMut<ParticleMeshes>,
+    query_emitter: Query<(Entity, &ParticleEmitter), With<Handle<ParticleMaterial>>>,
+) {
+    for event in effect_events.iter() {
+        let handle = match event {
+            AssetEvent::Modified { handle } => handle,
+            _ => continue,
+        };
+
+        cache.meshes.remove(handle);
+        for (entity, emitter) in query_emitter.iter() {
+            if emitter.effect == *handle {
+                commands
+                    .entity(entity)
+                    .remove::<Handle<Mesh>>()
+                    .remove::<Handle<ParticleMaterial>>()
+                    .remove::<ParticleBurst>();
+            }
+        }
+    }
+}
+
+fn extract_particle_time(mut commands: Commands, time: Res<Time>) {
+    commands.insert_resource(ParticleTime {
+        seconds: time.seconds_since_startup() as f32,
+    });
+}
+
+fn prepare_particle_globals(
+    time: Res<ParticleTime>,
+    globals: Res<ParticleGlobals>,
+    render_queue: Res<RenderQueue>,
+) {
+    let value = ParticleGlobalsUniform { time: time.seconds };
+    render_queue.write_buffer(&globals.buffer, 0, value.as_std140().as_bytes());
+}
+
+fn sys_despawn_bursts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query_burst: Query<(Entity, &mut ParticleBurst)>,
+) {
+    for (entity, mut burst) in query_burst.iter_mut() {
+        if burst.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Plugins
+
+pub struct ParticlesPlugin;
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<ParticleMaterial>::default())
+            .add_asset::<ParticleEffect>()
+            .init_asset_loader::<ParticleEffectLoader>()
+            .init_resource::<ParticleMeshes>()
+            .add_event::<SpawnParticles>()
+            .add_system(sys_spawn_bursts)
+            .add_system(sys_build_emitters)
+            .add_system(sys_reload_effects)
+            .add_system(sys_despawn_bursts);
+        app.sub_app_mut(RenderApp)
+            .init_resource::<ParticleGlobals>()
+            .add_system_to_stage(RenderStage::Extract, extract_particle_time)
+            .add_system_to_stage(RenderStage::Prepare, prepare_particle_globals);
+    }
+}
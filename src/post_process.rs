@@ -0,0 +1,362 @@
+use bevy::{
+    asset::HandleId,
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        camera::RenderTarget,
+        render_asset::{PrepareAssetError, RenderAsset, RenderAssets},
+        render_resource::{
+            std140::{AsStd140, Std140},
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+            BufferBindingType, BufferInitDescriptor, BufferSize, BufferUsages, Extent3d,
+            SamplerBindingType, ShaderStages, TextureDescriptor, TextureDimension, TextureFormat,
+            TextureSampleType, TextureUsages, TextureViewDimension,
+        },
+        renderer::RenderDevice,
+        view::RenderLayers,
+    },
+    sprite::{
+        Material2d, Material2dPipeline, Material2dPlugin, MaterialMesh2dBundle, Mesh2dHandle,
+    },
+    window::WindowResized,
+};
+
+use crate::camera::*;
+use crate::shader_reload::POST_PROCESS_SHADER_HANDLE;
+use crate::state_scoped::*;
+
+// Components
+
+#[derive(Component)]
+pub struct PostProcessQuad {
+    image: Handle<Image>,
+    camera: Entity,
+}
+
+// Events
+
+/// Briefly drains the colour out of the screen, used on cycle teleports.
+pub(crate) struct DesaturationPulse;
+
+// Resources
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct PostProcessIntensity {
+    pub speed: f32,
+    pub health: f32,
+    /// Left of the last desaturation pulse, from 1 down to 0.
+    pulse: f32,
+}
+
+impl Default for PostProcessIntensity {
+    fn default() -> Self {
+        Self {
+            speed: 0.0,
+            health: 100.0,
+            pulse: 0.0,
+        }
+    }
+}
+
+// GAMEPLAY VARIABLES
+
+const POST_PROCESS_LAYER: u8 = 1;
+
+const BLUR_MIN_SPEED: f32 = 40.0;
+const BLUR_MAX_SPEED: f32 = 120.0;
+// Matches the speed at which `Actor` starts taking damage
+const ABERRATION_MIN_SPEED: f32 = 100.0;
+const ABERRATION_MAX_SPEED: f32 = 140.0;
+const PULSE_SECONDS: f32 = 1.2;
+
+// Material
+
+#[derive(Component, Debug, Clone, TypeUuid)]
+#[uuid = "5B2D3A0E-8F1C-4E0B-9D57-3C1A7E6F2B94"]
+pub struct PostProcessMaterial {
+    pub source: Handle<Image>,
+    pub blur: f32,
+    pub aberration: f32,
+    pub vignette: f32,
+    pub desaturation: f32,
+}
+
+#[derive(Clone)]
+pub struct GpuPostProcessMaterial {
+    bind_group: BindGroup,
+}
+
+impl RenderAsset for PostProcessMaterial {
+    type ExtractedAsset = PostProcessMaterial;
+    type PreparedAsset = GpuPostProcessMaterial;
+    type Param = (
+        SRes<RenderDevice>,
+        SRes<Material2dPipeline<Self>>,
+        SRes<RenderAssets<Image>>,
+    );
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        extracted_asset: Self::ExtractedAsset,
+        (render_device, material_pipeline, images): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let source = match images.get(&extracted_asset.source) {
+            Some(source) => source,
+            None => return Err(PrepareAssetError::RetryNextUpdate(extracted_asset)),
+        };
+
+        let value = Vec4::new(
+            extracted_asset.blur,
+            extracted_asset.aberration,
+            extracted_asset.vignette,
+            extracted_asset.desaturation,
+        );
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            contents: value.as_std140().as_bytes(),
+            label: Some("Post Process Settings Buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&source.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&source.sampler),
+                },
+            ],
+            label: Some("Post Process BindGroup"),
+            layout: &material_pipeline.material2d_layout,
+        });
+
+        Ok(GpuPostProcessMaterial { bind_group })
+    }
+}
+
+impl Material2d for PostProcessMaterial {
+    fn vertex_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(POST_PROCESS_SHADER_HANDLE.typed())
+    }
+    fn fragment_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(POST_PROCESS_SHADER_HANDLE.typed())
+    }
+
+    fn bind_group(render_asset: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &render_asset.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(Vec4::std140_size_static() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("Post Process BindGroup Layout"),
+        })
+    }
+}
+
+// Systems
+
+fn new_render_target(window: &Window) -> Image {
+    let size = Extent3d {
+        width: window.physical_width().max(1),
+        height: window.physical_height().max(1),
+        ..default()
+    };
+
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("Post Process Source"),
+            size,
+            dimension: TextureDimension::D2,
+            // Has to match the format the 3D pipelines are specialised for
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+
+    image
+}
+
+/// Renders every new game camera into a texture and puts that texture on screen
+/// through a fullscreen quad with `PostProcessMaterial`.
+fn sys_attach_post_process(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<PostProcessMaterial>>,
+    mut query_camera: Query<(Entity, &mut Camera, Option<&StateScoped>), Added<GameCamera>>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    for (entity, mut camera, scope) in query_camera.iter_mut() {
+        let image = images.add(new_render_target(window));
+        camera.target = RenderTarget::Image(image.clone());
+
+        let material = materials.add(PostProcessMaterial {
+            source: image.clone(),
+            blur: 0.0,
+            aberration: 0.0,
+            vignette: 0.0,
+            desaturation: 0.0,
+        });
+
+        let mut camera_2d = commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+        camera_2d.insert(RenderLayers::layer(POST_PROCESS_LAYER));
+        if let Some(scope) = scope {
+            camera_2d.insert(scope.clone());
+        }
+
+        let mut quad = commands.spawn_bundle(MaterialMesh2dBundle {
+            mesh: Mesh2dHandle(meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE)))),
+            material,
+            transform: Transform::from_scale(Vec3::new(window.width(), window.height(), 1.0)),
+            ..default()
+        });
+        quad.insert(RenderLayers::layer(POST_PROCESS_LAYER))
+            .insert(PostProcessQuad {
+                image,
+                camera: entity,
+            });
+        if let Some(scope) = scope {
+            quad.insert(scope.clone());
+        }
+    }
+}
+
+fn sys_resize_targets(
+    mut resize_events: EventReader<WindowResized>,
+    windows: Res<Windows>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<PostProcessMaterial>>,
+    mut query_camera: Query<&mut Camera>,
+    mut query_quad: Query<(
+        &mut PostProcessQuad,
+        &Handle<PostProcessMaterial>,
+        &mut Transform,
+    )>,
+) {
+    if resize_events.iter().count() == 0 {
+        return;
+    }
+
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    // A new image rather than resizing the old one in place, so the material's bind group
+    // waits for the new texture instead of holding on to the old one
+    for (mut quad, material, mut transform) in query_quad.iter_mut() {
+        let image = images.add(new_render_target(window));
+
+        if let Ok(mut camera) = query_camera.get_mut(quad.camera) {
+            camera.target = RenderTarget::Image(image.clone());
+        }
+        if let Some(material) = materials.get_mut(material) {
+            material.source = image.clone();
+        }
+        quad.image = image;
+        transform.scale = Vec3::new(window.width(), window.height(), 1.0);
+    }
+}
+
+fn sys_update_effects(
+    time: Res<Time>,
+    mut intensity: ResMut<PostProcessIntensity>,
+    mut pulse_events: EventReader<DesaturationPulse>,
+    mut materials: ResMut<Assets<PostProcessMaterial>>,
+) {
+    if pulse_events.iter().count() > 0 {
+        intensity.pulse = 1.0;
+    }
+    intensity.pulse = (intensity.pulse - time.delta_seconds() / PULSE_SECONDS).max(0.0);
+
+    let blur =
+        ((intensity.speed - BLUR_MIN_SPEED) / (BLUR_MAX_SPEED - BLUR_MIN_SPEED)).clamp(0.0, 1.0);
+    let aberration = ((intensity.speed - ABERRATION_MIN_SPEED)
+        / (ABERRATION_MAX_SPEED - ABERRATION_MIN_SPEED))
+        .clamp(0.0, 1.0);
+    let vignette = ((100.0 - intensity.health) / 100.0).clamp(0.0, 1.0);
+    let desaturation = intensity.pulse;
+
+    // Mutable access re-uploads the material, so only touch the ones that are off
+    let stale: Vec<HandleId> = materials
+        .iter()
+        .filter(|(_, material)| {
+            material.blur != blur
+                || material.aberration != aberration
+                || material.vignette != vignette
+                || material.desaturation != desaturation
+        })
+        .map(|(id, _)| id)
+        .collect();
+
+    for id in stale {
+        if let Some(material) = materials.get_mut(id) {
+            material.blur = blur;
+            material.aberration = aberration;
+            material.vignette = vignette;
+            material.desaturation = desaturation;
+        }
+    }
+}
+
+// Plugins
+
+pub struct PostProcessPlugin;
+impl Plugin for PostProcessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(Material2dPlugin::<PostProcessMaterial>::default())
+            .add_event::<DesaturationPulse>()
+            .init_resource::<PostProcessIntensity>()
+            .add_system(sys_attach_post_process)
+            .add_system(sys_resize_targets)
+            .add_system(sys_update_effects);
+    }
+}
@@ -0,0 +1,44 @@
+use std::{fs, path::PathBuf};
+
+use bevy::prelude::*;
+
+#[cfg(not(test))]
+const SAVE_DIR: &str = "saves";
+// Tests must never touch a player's saves
+#[cfg(test)]
+const SAVE_DIR: &str = "target/test-saves";
+
+fn save_path(name: &str) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(name)
+}
+
+pub(crate) fn read_save(name: &str) -> Option<String> {
+    fs::read_to_string(save_path(name)).ok()
+}
+
+pub(crate) fn write_save(name: &str, contents: &str) {
+    let result = fs::create_dir_all(SAVE_DIR).and_then(|_| fs::write(save_path(name), contents));
+
+    if let Err(error) = result {
+        warn!("failed to write save {}: {}", name, error);
+    }
+}
+
+pub(crate) fn remove_save(name: &str) {
+    let _ = fs::remove_file(save_path(name));
+}
+
+/// Parses `key value` lines as written by the save files.
+pub(crate) fn parse_fields(contents: &str) -> Vec<(&str, &str)> {
+    contents
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .collect()
+}
+
+pub(crate) fn field<T: std::str::FromStr>(fields: &[(&str, &str)], key: &str) -> Option<T> {
+    fields
+        .iter()
+        .find(|(k, _)| *k == key)
+        .and_then(|(_, v)| v.trim().parse().ok())
+}
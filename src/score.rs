@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+use heron::*;
+
+use crate::app_states::*;
+use crate::falling::*;
+use crate::shaft::*;
+
+// Components
+
+#[derive(Component, Default)]
+pub struct ScoreText;
+
+// Resources
+
+/// Points of a run. Held speed and pickups are worth more the longer the combo runs.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Score {
+    pub points: f32,
+    /// Pickups chained without slowing down.
+    pub combo: u32,
+    pub near_misses: u32,
+    combo_timer: f32,
+    /// Speed at the last pickup of the combo, dropping well below it breaks the combo.
+    combo_speed: f32,
+    /// Height of the player last frame, to tell which floors were passed.
+    last_y: Option<f32>,
+}
+
+impl Score {
+    pub fn multiplier(&self) -> f32 {
+        (1.0 + COMBO_STEP * self.combo.saturating_sub(1) as f32).min(MAX_MULTIPLIER)
+    }
+
+    pub fn pickup(&mut self, speed: f32) {
+        self.combo = match self.combo_timer > 0.0 {
+            true => self.combo + 1,
+            false => 1,
+        };
+        self.combo_timer = COMBO_WINDOW;
+        self.combo_speed = speed;
+        self.points += PICKUP_POINTS * self.multiplier();
+    }
+
+    pub fn near_miss(&mut self) {
+        self.near_misses += 1;
+        self.points += NEAR_MISS_POINTS * self.multiplier();
+    }
+
+    fn hold_speed(&mut self, speed: f32, delta: f32) {
+        self.points += speed * SPEED_POINTS * delta * self.multiplier();
+    }
+
+    fn brake(&mut self, delta: f32) {
+        self.points = (self.points - BRAKE_PENALTY * delta).max(0.0);
+    }
+
+    fn update_combo(&mut self, speed: f32, delta: f32) {
+        self.combo_timer -= delta;
+
+        if self.combo_timer <= 0.0 || speed < self.combo_speed * COMBO_SLOWDOWN {
+            self.combo = 0;
+            self.combo_timer = 0.0;
+        }
+    }
+}
+
+// GAMEPLAY VARIABLES
+
+/// Speed only scores while health stays above this.
+const HEALTH_MARGIN: f32 = 50.0;
+const SPEED_POINTS: f32 = 0.1;
+const PICKUP_POINTS: f32 = 100.0;
+const NEAR_MISS_POINTS: f32 = 50.0;
+/// Horizontal distance to a ring cube that counts as a near miss when passing its floor.
+const NEAR_MISS_DISTANCE: f32 = 1.6;
+const BRAKE_PENALTY: f32 = 20.0;
+const COMBO_WINDOW: f32 = 5.0;
+const COMBO_SLOWDOWN: f32 = 0.8;
+const COMBO_STEP: f32 = 0.25;
+const MAX_MULTIPLIER: f32 = 4.0;
+
+// Systems
+
+fn sys_update_score(
+    time: Res<Time>,
+    shaft: Res<Shaft>,
+    mut state: ResMut<FallingRun>,
+    query_actor: Query<(&Transform, &Velocity, &Actor)>,
+) {
+    let delta = time.delta_seconds();
+
+    for (transform, velocity, actor) in query_actor.iter() {
+        let speed = -velocity.linear.y;
+        let y = transform.translation.y;
+
+        state.score.update_combo(speed, delta);
+
+        if actor.health > HEALTH_MARGIN && speed > 0.0 {
+            state.score.hold_speed(speed, delta);
+        }
+
+        if actor.braking {
+            state.score.brake(delta);
+        }
+
+        // Every floor passed since the last frame, teleports jump back up and pass none
+        if let Some(last_y) = state.score.last_y {
+            let first = (y / FLOOR_HEIGHT).ceil().max(0.0) as u32;
+            let last = (last_y / FLOOR_HEIGHT).floor().max(0.0) as u32;
+
+            if last_y > y {
+                for floor in first..=last.min(shaft.floors.saturating_sub(1)) {
+                    let clearance = shaft.ring_clearance(floor, transform.translation);
+                    if clearance.map_or(false, |clearance| clearance < NEAR_MISS_DISTANCE) {
+                        state.score.near_miss();
+                    }
+                }
+            }
+        }
+        state.score.last_y = Some(y);
+    }
+}
+
+fn sys_update_score_text(
+    state: Res<FallingRun>,
+    mut query_text: Query<&mut Text, With<ScoreText>>,
+) {
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = match state.score.combo > 1 {
+            true => format!(
+                "score   {}   x{}",
+                state.score.points as u32,
+                state.score.multiplier()
+            ),
+            false => format!("score   {}", state.score.points as u32),
+        };
+    }
+}
+
+// Plugins
+
+pub struct ScorePlugin;
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::FallingGame)
+                .with_system(sys_update_score)
+                .with_system(sys_update_score_text),
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+
+use crate::saves::*;
+
+const SETTINGS_SAVE: &str = "settings.txt";
+
+// Resources
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Settings {
+    pub music_volume: f32,
+    /// Sound effects and the UI.
+    pub sound_volume: f32,
+    /// Screams and narration.
+    pub voice_volume: f32,
+    pub screen_shake: bool,
+    pub fullscreen: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            music_volume: 1.0,
+            sound_volume: 1.0,
+            voice_volume: 1.0,
+            screen_shake: true,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let contents = read_save(SETTINGS_SAVE).unwrap_or_default();
+        let fields = parse_fields(&contents);
+        let defaults = Self::default();
+
+        Self {
+            music_volume: field(&fields, "music_volume").unwrap_or(defaults.music_volume),
+            sound_volume: field(&fields, "sound_volume").unwrap_or(defaults.sound_volume),
+            voice_volume: field(&fields, "voice_volume").unwrap_or(defaults.voice_volume),
+            screen_shake: field(&fields, "screen_shake").unwrap_or(defaults.screen_shake),
+            fullscreen: field(&fields, "fullscreen").unwrap_or(defaults.fullscreen),
+        }
+    }
+
+    pub fn save(&self) {
+        let contents = format!(
+            "music_volume {}\nsound_volume {}\nvoice_volume {}\nscreen_shake {}\nfullscreen {}\n",
+            self.music_volume,
+            self.sound_volume,
+            self.voice_volume,
+            self.screen_shake,
+            self.fullscreen
+        );
+
+        write_save(SETTINGS_SAVE, &contents);
+    }
+}
+
+// Systems
+
+fn sys_apply_window_mode(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = match settings.fullscreen {
+            true => WindowMode::BorderlessFullscreen,
+            false => WindowMode::Windowed,
+        };
+
+        if window.mode() != mode {
+            window.set_mode(mode);
+        }
+    }
+}
+
+// Plugins
+
+pub struct SettingsPlugin;
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::load())
+            .add_system(sys_apply_window_mode);
+    }
+}
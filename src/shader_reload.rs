@@ -0,0 +1,229 @@
+use bevy::{
+    asset::HandleId,
+    pbr::CLUSTERED_FORWARD_STORAGE_BUFFER_COUNT,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_resource::{BufferBindingType, Shader, ShaderProcessor},
+        renderer::RenderDevice,
+    },
+    utils::HashMap,
+};
+
+use crate::indoctrination::UiFixedZ;
+
+// Shaders
+
+// Materials render with these handles. They only ever receive sources that compiled,
+// so a broken edit leaves the last good pipeline in place.
+pub(crate) const BLOODFIELD_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x62b1_9d4e_0c37_a815);
+pub(crate) const POST_PROCESS_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x1e7a_c350_94fd_2b68);
+pub(crate) const FIRE_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x9c42_0b6f_e815_d37a);
+pub(crate) const PARTICLES_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x4d83_f1a6_27c9_5e0b);
+pub(crate) const INSTANCING_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0xb6e0_2a95_7d1c_43f8);
+
+const WATCHED_SHADERS: [(&str, HandleUntyped); 5] = [
+    ("shaders/bloodfield.wgsl", BLOODFIELD_SHADER_HANDLE),
+    ("shaders/post_process.wgsl", POST_PROCESS_SHADER_HANDLE),
+    ("shaders/fire.wgsl", FIRE_SHADER_HANDLE),
+    ("shaders/particles.wgsl", PARTICLES_SHADER_HANDLE),
+    ("shaders/instancing.wgsl", INSTANCING_SHADER_HANDLE),
+];
+
+// Components
+
+#[derive(Component, Default)]
+pub struct ShaderErrorText;
+
+// Resources
+
+struct WatchedShader {
+    path: &'static str,
+    source: Handle<Shader>,
+    target: HandleUntyped,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct ShaderReloader {
+    watched: Vec<WatchedShader>,
+}
+
+// Validation
+
+/// Shader defs the mesh pipelines specialise with that the imported bevy shaders branch on.
+/// Tangent and skinning defs are left out, the watched shaders are only drawn with meshes
+/// that have neither.
+fn pipeline_shader_defs(render_device: &RenderDevice) -> Vec<String> {
+    let mut shader_defs = Vec::new();
+
+    if !matches!(
+        render_device.get_supported_read_only_binding_type(CLUSTERED_FORWARD_STORAGE_BUFFER_COUNT),
+        BufferBindingType::Storage { .. }
+    ) {
+        shader_defs.push(String::from("NO_STORAGE_BUFFERS_SUPPORT"));
+    }
+
+    shader_defs
+}
+
+/// Runs the shader through the same preprocessing and validation the pipeline cache does.
+fn validate_shader(
+    shader: &Shader,
+    shader_defs: &[String],
+    shaders: &Assets<Shader>,
+) -> Result<(), String> {
+    let mut all_shaders = HashMap::default();
+    let mut import_handles = HashMap::default();
+
+    for (id, shader) in shaders.iter() {
+        let handle = Handle::<Shader>::weak(id);
+        if let Some(import) = shader.import_path() {
+            import_handles.insert(import.clone(), handle.clone());
+        }
+        all_shaders.insert(handle, shader.clone());
+    }
+
+    let processed = ShaderProcessor::default()
+        .process(shader, shader_defs, &all_shaders, &import_handles)
+        .map_err(|error| error.to_string())?;
+
+    processed.reflect().map_err(|error| error.to_string())?;
+
+    Ok(())
+}
+
+// Systems
+
+fn sys_watch_shaders(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut reloader: ResMut<ShaderReloader>,
+) {
+    for (path, target) in WATCHED_SHADERS {
+        reloader.watched.push(WatchedShader {
+            path,
+            source: asset_server.load(path),
+            target,
+            error: None,
+        });
+    }
+
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: 16.0,
+                    color: Color::rgb(1.0, 0.3, 0.3),
+                },
+                TextAlignment::default(),
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                max_size: Size::new(Val::Percent(90.0), Val::Undefined),
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(UiFixedZ { z: 200.0 })
+        .insert(ShaderErrorText)
+        .insert(Name::new("Shader Error Overlay"));
+}
+
+fn sys_reload_shaders(
+    mut shader_events: EventReader<AssetEvent<Shader>>,
+    render_device: Res<RenderDevice>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut reloader: ResMut<ShaderReloader>,
+) {
+    let changed: Vec<HandleId> = shader_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => Some(handle.id),
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+
+    if changed.is_empty() {
+        return;
+    }
+
+    let shader_defs = pipeline_shader_defs(&render_device);
+
+    for watched in reloader.watched.iter_mut() {
+        if !changed.contains(&watched.source.id) {
+            continue;
+        }
+
+        let shader = match shaders.get(&watched.source) {
+            Some(shader) => shader.clone(),
+            None => continue,
+        };
+
+        match validate_shader(&shader, &shader_defs, &shaders) {
+            Ok(()) => {
+                if watched.error.take().is_some() {
+                    info!("shader {} fixed, reloading", watched.path);
+                }
+                shaders.set_untracked(watched.target.clone(), shader);
+            }
+            Err(error) => {
+                error!(
+                    "shader {} failed to compile, keeping the last good version:\n{}",
+                    watched.path, error
+                );
+                watched.error = Some(error);
+            }
+        }
+    }
+}
+
+fn sys_update_error_overlay(
+    reloader: Res<ShaderReloader>,
+    mut query: Query<(&mut Text, &mut Visibility), With<ShaderErrorText>>,
+) {
+    if !reloader.is_changed() {
+        return;
+    }
+
+    let errors: Vec<String> = reloader
+        .watched
+        .iter()
+        .filter_map(|watched| {
+            watched
+                .error
+                .as_ref()
+                .map(|error| format!("{}\n{}", watched.path, error))
+        })
+        .collect();
+
+    for (mut text, mut visibility) in query.iter_mut() {
+        text.sections[0].value = errors.join("\n\n");
+        visibility.is_visible = !errors.is_empty();
+    }
+}
+
+// Plugins
+
+pub struct ShaderReloadPlugin;
+impl Plugin for ShaderReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShaderReloader>()
+            .add_startup_system(sys_watch_shaders)
+            .add_system(sys_reload_shaders)
+            .add_system(sys_update_error_overlay);
+    }
+}
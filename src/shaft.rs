@@ -0,0 +1,516 @@
+use bevy::{
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::view::NoFrustumCulling,
+    utils::{HashMap, HashSet},
+};
+use bevy_prototype_debug_lines::DebugLines;
+use heron::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::app_states::*;
+use crate::camera::*;
+use crate::endless::*;
+use crate::falling::*;
+use crate::fire::*;
+use crate::instancing::*;
+use crate::state_scoped::*;
+
+// Components
+
+/// Pooled floor light, moved to whichever floor of the streamed window maps to its slot.
+#[derive(Component)]
+pub struct Floor {
+    slot: u32,
+    index: Option<u32>,
+}
+
+/// Instanced ring cubes of every streamed floor with the given parity, one entity per colour.
+#[derive(Component)]
+pub struct RingCubes {
+    parity: u32,
+    color: Color,
+}
+
+// Resources
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum RingPattern {
+    All,
+    Hidden,
+    /// Only every nth ring cube of the whole shaft is shown.
+    EveryNth(u32),
+    /// Roughly this share of the ring cubes is hidden, the same ones on every pass.
+    HiddenShare(f32),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct PickupLayout {
+    /// Floors from one pickup to the next.
+    pub spacing: u32,
+    /// Share of speed pickups, `None` rolls every type evenly.
+    pub speed_share: Option<f32>,
+}
+
+impl Default for PickupLayout {
+    fn default() -> Self {
+        Self {
+            spacing: 3,
+            speed_share: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PickupSpawn {
+    floor: u32,
+    cube_type: CubeType,
+    translation: Vec3,
+}
+
+/// Layout and streaming state of the shaft. The layout is plain data generated from the
+/// seed, only the floors and pickups around the player exist as entities.
+pub(crate) struct Shaft {
+    pub floors: u32,
+    pub ring_pattern: RingPattern,
+    pub ring_speed: f32,
+    pub pickup_layout: PickupLayout,
+    /// Sorted by floor.
+    pickups: Vec<PickupSpawn>,
+    collected: HashSet<usize>,
+    spawned: HashMap<usize, Entity>,
+    ring_angles: [f32; 2],
+    ring_spin: Quat,
+}
+
+impl Shaft {
+    pub fn new(seed: u64, floors: u32) -> Self {
+        Self {
+            floors,
+            ring_pattern: RingPattern::All,
+            ring_speed: 1.0,
+            pickup_layout: PickupLayout::default(),
+            pickups: generate_pickups(seed, floors, PickupLayout::default()),
+            collected: HashSet::default(),
+            spawned: HashMap::default(),
+            ring_angles: [0.0; 2],
+            ring_spin: Quat::IDENTITY,
+        }
+    }
+
+    /// Lays the pickups out again from `seed`, returning the pickup entities to despawn.
+    pub fn relayout(&mut self, seed: u64) -> Vec<Entity> {
+        self.pickups = generate_pickups(seed, self.floors, self.pickup_layout);
+        self.collected.clear();
+        self.spawned.drain().map(|(_, entity)| entity).collect()
+    }
+
+    /// Keeps a collected pickup from streaming back in.
+    pub fn collect(&mut self, entity: Entity) {
+        let index = self
+            .spawned
+            .iter()
+            .find(|(_, spawned)| **spawned == entity)
+            .map(|(index, _)| *index);
+
+        if let Some(index) = index {
+            self.spawned.remove(&index);
+            self.collected.insert(index);
+        }
+    }
+
+    /// Horizontal distance from `point` to the closest visible cube of the floor's ring.
+    pub fn ring_clearance(&self, floor: u32, point: Vec3) -> Option<f32> {
+        let angle = self.ring_angles[floor as usize % 2];
+        let point = Vec2::new(point.x, point.z);
+
+        // Cubes come in pairs sharing a position, see `sys_spawn_shaft`
+        (0..CUBES_PER_RING / 2)
+            .filter(|i| {
+                let cube = floor * CUBES_PER_RING + i * 2;
+                self.ring_visible(cube) || self.ring_visible(cube + 1)
+            })
+            .map(|i| {
+                let angle = std::f32::consts::PI * 2.0 / 11.0 * (i as f32) + angle;
+                Vec2::new(f32::sin(angle), f32::cos(angle)).distance(point / RADIUS) * RADIUS
+            })
+            .reduce(f32::min)
+    }
+
+    fn ring_visible(&self, cube: u32) -> bool {
+        match self.ring_pattern {
+            RingPattern::All => true,
+            RingPattern::Hidden => false,
+            RingPattern::EveryNth(n) => cube % n == 0,
+            RingPattern::HiddenShare(share) => {
+                let hash = cube.wrapping_mul(0x9e37_79b1) >> 16;
+                hash as f32 / 65536.0 >= share
+            }
+        }
+    }
+}
+
+/// The default layout rolls the same sequence the shaft was always built with, so a seed
+/// reproduces its layout.
+fn generate_pickups(seed: u64, floors: u32, layout: PickupLayout) -> Vec<PickupSpawn> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pickups = Vec::new();
+
+    for j in 0..floors {
+        if j % layout.spacing != 0 {
+            continue;
+        }
+
+        let cube_type = match layout.speed_share {
+            None => match rng.gen_range(0..3) {
+                0 => CubeType::Brake,
+                1 => CubeType::Health,
+                2 => CubeType::Speed,
+                _ => CubeType::Environment,
+            },
+            Some(share) if rng.gen::<f32>() < share => CubeType::Speed,
+            Some(_) => match rng.gen_bool(0.5) {
+                true => CubeType::Health,
+                false => CubeType::Brake,
+            },
+        };
+
+        let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+        let radius = rng.gen_range(0.0..(RADIUS - 1.0));
+
+        pickups.push(PickupSpawn {
+            floor: j,
+            cube_type,
+            translation: Vec3::new(
+                f32::sin(angle) * radius,
+                j as f32 * FLOOR_HEIGHT,
+                f32::cos(angle) * radius,
+            ),
+        });
+    }
+
+    pickups
+}
+
+/// Handles shared by every streamed pickup and ring.
+struct ShaftAssets {
+    sphere: Handle<Mesh>,
+    health: Handle<StandardMaterial>,
+    brake: Handle<StandardMaterial>,
+    // Speed spheres push the player towards the damage threshold, so they burn
+    speed: Handle<FireMaterial>,
+    /// Cube transforms of one ring at floor height 0.
+    ring: Vec<Transform>,
+}
+
+// GAMEPLAY VARIABLES
+
+pub(crate) const SHAFT_FLOORS: u32 = 300;
+pub(crate) const FLOOR_HEIGHT: f32 = 10.0;
+
+const CUBES_PER_RING: u32 = 22;
+// The player falls downwards, so most of the window is below them
+const FLOORS_AHEAD: i64 = 60;
+const FLOORS_BEHIND: i64 = 4;
+const FLOOR_POOL: u32 = (FLOORS_AHEAD + FLOORS_BEHIND + 1) as u32;
+const PARKED_Y: f32 = -10_000.0;
+
+// Systems
+
+fn sys_spawn_shaft(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut fire_materials: ResMut<Assets<FireMaterial>>,
+) {
+    let pickup_material = |color: Color| StandardMaterial {
+        base_color: color,
+        reflectance: 0.7,
+        alpha_mode: AlphaMode::Opaque,
+        perceptual_roughness: 0.08,
+        ..default()
+    };
+
+    let mut ring = Vec::with_capacity(CUBES_PER_RING as usize);
+    for i in 0..11 {
+        let angle = std::f32::consts::PI * 2.0 / 11.0 * (i as f32);
+
+        let x = f32::sin(angle) * RADIUS;
+        let z = f32::cos(angle) * RADIUS;
+
+        let rotation = Quat::from_rotation_y(std::f32::consts::PI / 4.0);
+
+        ring.push(Transform::from_xyz(x, 0.0, z));
+        ring.push(Transform::from_xyz(x, 0.0, z).with_rotation(rotation));
+    }
+
+    commands.insert_resource(ShaftAssets {
+        sphere: meshes.add(Mesh::from(shape::UVSphere {
+            sectors: 128,
+            stacks: 64,
+            ..default()
+        })),
+        health: materials.add(pickup_material(CubeType::Health.color())),
+        brake: materials.add(pickup_material(CubeType::Brake.color())),
+        speed: fire_materials.add(FireMaterial {
+            base_color: CubeType::Speed.color(),
+            ..default()
+        }),
+        ring,
+    });
+
+    // Every ring cube is an instance of one mesh, drawn once per colour
+    let cube_mesh = meshes.add(Mesh::from(shape::Cube { size: 0.8 }));
+    for (parity, color) in [
+        (0, Color::rgb(0.8, 0.1, 0.1)),
+        (1, Color::rgb(0.8, 0.2, 0.1)),
+    ] {
+        commands
+            .spawn_bundle((
+                cube_mesh.clone(),
+                Transform::identity(),
+                GlobalTransform::identity(),
+                Visibility::default(),
+                ComputedVisibility::default(),
+                MeshInstances::default(),
+                NoFrustumCulling,
+                NotShadowCaster,
+            ))
+            .insert(RingCubes { parity, color })
+            .insert(StateScoped(AppState::FallingGame));
+    }
+
+    for slot in 0..FLOOR_POOL {
+        commands
+            .spawn_bundle(PointLightBundle {
+                point_light: PointLight {
+                    intensity: 2000.0,
+                    shadows_enabled: false,
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(0.0, PARKED_Y, 0.0),
+                ..Default::default()
+            })
+            .insert(Floor { slot, index: None })
+            .insert(StateScoped(AppState::FallingGame));
+    }
+}
+
+fn spawn_pickup(commands: &mut Commands, assets: &ShaftAssets, pickup: &PickupSpawn) -> Entity {
+    let transform = Transform::from_translation(pickup.translation);
+
+    let mut sphere = match pickup.cube_type {
+        CubeType::Speed => commands.spawn_bundle(MaterialMeshBundle {
+            mesh: assets.sphere.clone(),
+            material: assets.speed.clone(),
+            transform,
+            ..default()
+        }),
+        CubeType::Brake => commands.spawn_bundle(PbrBundle {
+            mesh: assets.sphere.clone(),
+            material: assets.brake.clone(),
+            transform,
+            ..default()
+        }),
+        _ => commands.spawn_bundle(PbrBundle {
+            mesh: assets.sphere.clone(),
+            material: assets.health.clone(),
+            transform,
+            ..default()
+        }),
+    };
+
+    sphere
+        .insert(Cube {
+            cube_type: pickup.cube_type,
+        })
+        .insert(CollisionShape::Sphere { radius: 1.0 })
+        .insert(
+            CollisionLayers::none()
+                .with_group(Layer::World)
+                .with_masks(&[Layer::Player]),
+        )
+        .insert(RigidBody::Sensor)
+        .insert(StateScoped(AppState::FallingGame))
+        .id()
+}
+
+/// Moves the floor pool and spawns or despawns pickups so only the window of floors around
+/// the player exists.
+fn sys_stream_shaft(
+    mut commands: Commands,
+    mut shaft: ResMut<Shaft>,
+    assets: Res<ShaftAssets>,
+    query_target: Query<&Transform, With<CameraTarget>>,
+    mut query_floor: Query<(&mut Transform, &mut Floor), Without<CameraTarget>>,
+) {
+    let target = match query_target.iter().next() {
+        Some(target) => target,
+        None => return,
+    };
+
+    let current = (target.translation.y / FLOOR_HEIGHT).floor() as i64;
+    let lowest = current - FLOORS_AHEAD;
+    let highest = current + FLOORS_BEHIND;
+    let in_shaft = |floor: i64| floor >= 0 && floor < shaft.floors as i64;
+
+    // The window is exactly as large as the pool, so each floor has one slot to go to
+    for (mut transform, mut floor) in query_floor.iter_mut() {
+        let offset = (floor.slot as i64 - lowest).rem_euclid(FLOOR_POOL as i64);
+        let index = lowest + offset;
+
+        let index = match in_shaft(index) {
+            true => Some(index as u32),
+            false => None,
+        };
+        if floor.index == index {
+            continue;
+        }
+
+        floor.index = index;
+        transform.translation.y = match index {
+            Some(index) => index as f32 * FLOOR_HEIGHT,
+            None => PARKED_Y,
+        };
+    }
+
+    let shaft = &mut *shaft;
+
+    shaft.spawned.retain(|index, entity| {
+        let floor = shaft.pickups[*index].floor as i64;
+        let keep = floor >= lowest && floor <= highest;
+        if !keep {
+            commands.entity(*entity).despawn_recursive();
+        }
+        keep
+    });
+
+    let first = shaft
+        .pickups
+        .partition_point(|pickup| (pickup.floor as i64) < lowest);
+    for index in first..shaft.pickups.len() {
+        let pickup = &shaft.pickups[index];
+        if pickup.floor as i64 > highest {
+            break;
+        }
+        if shaft.collected.contains(&index) || shaft.spawned.contains_key(&index) {
+            continue;
+        }
+
+        let entity = spawn_pickup(&mut commands, &assets, pickup);
+        shaft.spawned.insert(index, entity);
+    }
+}
+
+fn sys_animate_shaft(
+    time: Res<Time>,
+    state: Res<FallingRun>,
+    mut shaft: ResMut<Shaft>,
+    query_floor: Query<&Floor>,
+    assets: Res<ShaftAssets>,
+    mut lines: ResMut<DebugLines>,
+) {
+    let rotating = match state.mode {
+        RunMode::Story | RunMode::TimeAttack => matches!(state.cycle_number, 0 | 2 | 4),
+        RunMode::Endless => true,
+    };
+
+    if rotating {
+        let delta = time.delta_seconds() * shaft.ring_speed;
+
+        for (parity, angle) in shaft.ring_angles.iter_mut().enumerate() {
+            let mut dir = match parity {
+                0 => -1.0,
+                _ => 1.0,
+            };
+
+            if state.mode != RunMode::Endless && state.cycle_number == 4 {
+                dir = -1.0;
+            }
+
+            *angle += dir * 1.0 * delta;
+        }
+
+        shaft.ring_spin *= Quat::from_rotation_x(1.0 * delta) * Quat::from_rotation_y(0.7 * delta);
+    }
+
+    if state.mode != RunMode::Endless && (state.cycle_number == 2 || state.cycle_number == 6) {
+        for index in query_floor.iter().filter_map(|floor| floor.index) {
+            let y = index as f32 * FLOOR_HEIGHT;
+            let rotation_quat = Quat::from_rotation_y(shaft.ring_angles[index as usize % 2]);
+
+            // Spawn Circle of Cubes
+            for i in 0..11 {
+                let index_end;
+                if i + 4 < 11 {
+                    index_end = i + 4;
+                } else {
+                    index_end = i + 4 - 11;
+                }
+
+                let mut start_line = rotation_quat.mul_vec3(assets.ring[i * 2].translation) * 2.0;
+                start_line.y = y;
+
+                let mut end_line =
+                    rotation_quat.mul_vec3(assets.ring[index_end * 2].translation) * 2.0;
+                end_line.y = y;
+
+                lines.line_colored(start_line, end_line, 0.1, Color::rgba(0.1, 0.1, 0.1, 0.8));
+            }
+        }
+    }
+}
+
+fn sys_update_ring_instances(
+    shaft: Res<Shaft>,
+    assets: Res<ShaftAssets>,
+    query_floor: Query<&Floor>,
+    mut query_rings: Query<(&RingCubes, &mut MeshInstances)>,
+) {
+    for (rings, mut instances) in query_rings.iter_mut() {
+        instances.0.clear();
+
+        let rotation = Quat::from_rotation_y(shaft.ring_angles[rings.parity as usize]);
+
+        for index in query_floor.iter().filter_map(|floor| floor.index) {
+            if index % 2 != rings.parity {
+                continue;
+            }
+
+            let y = Vec3::new(0.0, index as f32 * FLOOR_HEIGHT, 0.0);
+            for (cube, transform) in assets.ring.iter().enumerate() {
+                if !shaft.ring_visible(index * CUBES_PER_RING + cube as u32) {
+                    continue;
+                }
+
+                instances.0.push(MeshInstance::new(
+                    rotation * transform.translation + y,
+                    rotation * transform.rotation * shaft.ring_spin,
+                    1.0,
+                    rings.color,
+                ));
+            }
+        }
+    }
+}
+
+fn sys_end_shaft(mut commands: Commands) {
+    commands.remove_resource::<Shaft>();
+    commands.remove_resource::<ShaftAssets>();
+}
+
+// Plugins
+
+pub struct ShaftPlugin;
+impl Plugin for ShaftPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::FallingGame).with_system(sys_spawn_shaft))
+            .add_system_set(
+                SystemSet::on_update(AppState::FallingGame)
+                    .with_system(sys_stream_shaft)
+                    .with_system(sys_animate_shaft)
+                    .with_system(sys_update_ring_instances),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::FallingGame).with_system(sys_end_shaft));
+    }
+}
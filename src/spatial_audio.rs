@@ -0,0 +1,292 @@
+use std::{cmp::Ordering, marker::PhantomData};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_kira_audio::{AudioApp, AudioChannel};
+
+use crate::settings::*;
+
+// Channels
+
+pub struct SpatialSlot<const N: usize>;
+
+const SLOTS: usize = 4;
+/// How long a one-shot keeps its slot, longer sounds get cut off.
+const ONE_SHOT_SECONDS: f32 = 3.0;
+
+// Components
+
+#[derive(Component, Default)]
+pub struct AudioListener;
+
+#[derive(Component, Clone)]
+pub struct AudioEmitter {
+    pub sound: &'static str,
+    pub range: f32,
+    pub volume: f32,
+    /// Emitters with a higher priority get a slot first, however far away they are.
+    pub priority: u8,
+}
+
+/// Plays the emitter's sound once instead of looping it, then despawns.
+#[derive(Component)]
+pub(crate) struct OneShotEmitter {
+    pub playback_rate: f32,
+    lifetime: Timer,
+}
+
+impl OneShotEmitter {
+    pub fn new(playback_rate: f32) -> Self {
+        Self {
+            playback_rate,
+            lifetime: Timer::from_seconds(ONE_SHOT_SECONDS, false),
+        }
+    }
+}
+
+// Resources
+
+#[derive(Default)]
+pub(crate) struct ListenerTransform {
+    pub transform: Option<GlobalTransform>,
+}
+
+#[derive(Default)]
+struct SpatialSlots {
+    entities: [Option<Entity>; SLOTS],
+    applied: [(f32, f32); SLOTS],
+}
+
+#[derive(SystemParam)]
+struct SpatialChannels<'w, 's> {
+    slot_0: Res<'w, AudioChannel<SpatialSlot<0>>>,
+    slot_1: Res<'w, AudioChannel<SpatialSlot<1>>>,
+    slot_2: Res<'w, AudioChannel<SpatialSlot<2>>>,
+    slot_3: Res<'w, AudioChannel<SpatialSlot<3>>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+macro_rules! with_slot {
+    ($channels:expr, $slot:expr, $channel:ident => $body:expr) => {
+        match $slot {
+            0 => {
+                let $channel = &$channels.slot_0;
+                $body
+            }
+            1 => {
+                let $channel = &$channels.slot_1;
+                $body
+            }
+            2 => {
+                let $channel = &$channels.slot_2;
+                $body
+            }
+            _ => {
+                let $channel = &$channels.slot_3;
+                $body
+            }
+        }
+    };
+}
+
+// Spatialisation
+
+/// How strongly an emitter in range holds on to a slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SlotClaim {
+    priority: u8,
+    playing: bool,
+    distance: f32,
+}
+
+/// Higher priorities first, then emitters already playing so a burst of one-shots doesn't
+/// cut off and restart a loop, then the nearest.
+fn claim_order(a: &SlotClaim, b: &SlotClaim) -> Ordering {
+    b.priority
+        .cmp(&a.priority)
+        .then(b.playing.cmp(&a.playing))
+        .then(a.distance.total_cmp(&b.distance))
+}
+
+/// Returns volume and kira panning (0 is left, 0.5 is centre, 1 is right) of a sound at `position`.
+fn spatialise(listener: &GlobalTransform, position: Vec3, range: f32, volume: f32) -> (f32, f32) {
+    let local = listener
+        .compute_matrix()
+        .inverse()
+        .transform_point3(position);
+    let distance = local.length();
+
+    if distance >= range {
+        return (0.0, 0.5);
+    }
+
+    let attenuation = (1.0 - distance / range).powi(2);
+    let panning = if distance > f32::EPSILON {
+        0.5 + 0.5 * (local.x / distance).clamp(-1.0, 1.0)
+    } else {
+        0.5
+    };
+
+    (volume * attenuation, panning)
+}
+
+// Systems
+
+fn sys_track_listener(
+    query_listener: Query<&GlobalTransform, With<AudioListener>>,
+    mut listener: ResMut<ListenerTransform>,
+) {
+    listener.transform = query_listener.iter().next().copied();
+}
+
+fn sys_update_emitters(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    listener: Res<ListenerTransform>,
+    settings: Res<Settings>,
+    query_emitters: Query<(
+        Entity,
+        &GlobalTransform,
+        &AudioEmitter,
+        Option<&OneShotEmitter>,
+    )>,
+    mut slots: ResMut<SpatialSlots>,
+    channels: SpatialChannels,
+) {
+    let mut nearest: Vec<(
+        Entity,
+        SlotClaim,
+        Vec3,
+        &AudioEmitter,
+        Option<&OneShotEmitter>,
+    )> = Vec::new();
+
+    if let Some(listener) = listener.transform {
+        for (entity, transform, emitter, one_shot) in query_emitters.iter() {
+            let distance = transform.translation.distance(listener.translation);
+            if distance < emitter.range {
+                let claim = SlotClaim {
+                    priority: emitter.priority,
+                    playing: slots.entities.contains(&Some(entity)),
+                    distance,
+                };
+                nearest.push((entity, claim, transform.translation, emitter, one_shot));
+            }
+        }
+    }
+
+    nearest.sort_by(|a, b| claim_order(&a.1, &b.1));
+    nearest.truncate(SLOTS);
+
+    // Release slots whose emitter is gone or no longer among the nearest
+    for slot in 0..SLOTS {
+        if let Some(entity) = slots.entities[slot] {
+            if !nearest.iter().any(|(e, _, _, _, _)| *e == entity) {
+                with_slot!(channels, slot, channel => channel.stop());
+                slots.entities[slot] = None;
+
+                // A one-shot that lost its slot would start over when it gets one back
+                if let Ok((_, _, _, Some(_))) = query_emitters.get(entity) {
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+
+    for (entity, _, position, emitter, one_shot) in nearest.iter() {
+        let slot = match slots.entities.iter().position(|e| *e == Some(*entity)) {
+            Some(slot) => slot,
+            None => {
+                let slot = slots.entities.iter().position(|e| e.is_none()).unwrap();
+                let source = asset_server.load(emitter.sound);
+                with_slot!(channels, slot, channel => {
+                    channel.set_volume(0.0);
+                    match one_shot {
+                        Some(one_shot) => {
+                            channel.set_playback_rate(one_shot.playback_rate);
+                            channel.play(source);
+                        }
+                        None => {
+                            channel.set_playback_rate(1.0);
+                            channel.play_looped(source);
+                        }
+                    }
+                });
+                slots.entities[slot] = Some(*entity);
+                slots.applied[slot] = (0.0, 0.5);
+                slot
+            }
+        };
+
+        let (volume, panning) = spatialise(
+            listener.transform.as_ref().unwrap(),
+            *position,
+            emitter.range,
+            emitter.volume * settings.sound_volume,
+        );
+
+        let (applied_volume, applied_panning) = slots.applied[slot];
+        if (volume - applied_volume).abs() > 0.01 || (panning - applied_panning).abs() > 0.01 {
+            with_slot!(channels, slot, channel => {
+                channel.set_volume(volume);
+                channel.set_panning(panning);
+            });
+            slots.applied[slot] = (volume, panning);
+        }
+    }
+}
+
+fn sys_expire_one_shots(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query_one_shots: Query<(Entity, &mut OneShotEmitter)>,
+) {
+    for (entity, mut one_shot) in query_one_shots.iter_mut() {
+        if one_shot.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Plugins
+
+pub struct SpatialAudioPlugin;
+impl Plugin for SpatialAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<SpatialSlot<0>>()
+            .add_audio_channel::<SpatialSlot<1>>()
+            .add_audio_channel::<SpatialSlot<2>>()
+            .add_audio_channel::<SpatialSlot<3>>()
+            .init_resource::<ListenerTransform>()
+            .init_resource::<SpatialSlots>()
+            .add_system(sys_track_listener)
+            .add_system(sys_update_emitters)
+            .add_system(sys_expire_one_shots);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(priority: u8, playing: bool, distance: f32) -> SlotClaim {
+        SlotClaim {
+            priority,
+            playing,
+            distance,
+        }
+    }
+
+    #[test]
+    fn slots_go_by_priority_then_stay_taken() {
+        let far_priority = claim(1, false, 300.0);
+        let playing = claim(0, true, 15.0);
+        let nearest = claim(0, false, 0.5);
+        let near = claim(0, false, 2.0);
+
+        let mut claims = vec![near, nearest, playing, far_priority];
+        claims.sort_by(claim_order);
+
+        assert_eq!(claims, vec![far_priority, playing, nearest, near]);
+    }
+}
@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+#[cfg(debug_assertions)]
+use bevy::utils::HashSet;
+
+use crate::app_states::*;
+
+// Components
+
+/// Despawns the entity, with its children, when the tagged state is exited.
+#[derive(Component, Clone, Debug)]
+pub(crate) struct StateScoped(pub AppState);
+
+// Systems
+
+fn despawn_state_scoped(state: AppState) -> impl FnMut(Commands, Query<(Entity, &StateScoped)>) {
+    move |mut commands: Commands, query: Query<(Entity, &StateScoped)>| {
+        for (e, scope) in query.iter() {
+            if scope.0 == state {
+                commands.entity(e).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct ScopeAudit {
+    state: Option<AppState>,
+    known: HashSet<Entity>,
+}
+
+#[cfg(debug_assertions)]
+fn sys_audit_state_scoped(
+    app_state: Res<State<AppState>>,
+    mut audit: Local<ScopeAudit>,
+    query: Query<(Entity, Option<&StateScoped>, Option<&Name>), Without<Parent>>,
+) {
+    let current = app_state.current();
+
+    if audit.state.as_ref() == Some(current) {
+        return;
+    }
+
+    if let Some(previous) = audit.state.as_ref() {
+        for (e, scope, name) in query.iter() {
+            if audit.known.contains(&e) {
+                continue;
+            }
+
+            match scope {
+                Some(scope) if scope.0 == *current => {}
+                Some(scope) => warn!(
+                    "entity {:?} ({:?}) scoped to {:?} survived {:?} -> {:?}",
+                    e, name, scope.0, previous, current
+                ),
+                None => warn!(
+                    "entity {:?} ({:?}) has no StateScoped and survived {:?} -> {:?}",
+                    e, name, previous, current
+                ),
+            }
+        }
+    }
+
+    audit.state = Some(current.clone());
+    audit.known = query
+        .iter()
+        .filter(|(_, scope, _)| scope.is_none())
+        .map(|(e, _, _)| e)
+        .collect();
+}
+
+// Plugins
+
+pub struct StateScopedPlugin;
+impl Plugin for StateScopedPlugin {
+    fn build(&self, app: &mut App) {
+        for state in AppState::ALL {
+            app.add_system_set(
+                SystemSet::on_exit(state.clone()).with_system(despawn_state_scoped(state)),
+            );
+        }
+
+        #[cfg(debug_assertions)]
+        app.add_system_to_stage(CoreStage::Last, sys_audit_state_scoped);
+    }
+}
@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+
+use crate::app_states::*;
+use crate::endless::*;
+use crate::falling::*;
+use crate::saves::*;
+
+const SPLITS_SAVE: &str = "splits.txt";
+
+// Components
+
+#[derive(Component, Default)]
+pub struct SplitText;
+
+// Resources
+
+/// Time spent in one cycle, from entering it to its teleport.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Split {
+    pub cycle: u8,
+    pub time: f32,
+}
+
+/// Best time through each cycle over every finished time attack, and how far any run got.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct BestSplits {
+    pub times: [Option<f32>; STORY_CYCLES as usize],
+    /// Highest cycle a time attack may start from.
+    pub unlocked: u8,
+}
+
+impl BestSplits {
+    pub fn load() -> Self {
+        let contents = read_save(SPLITS_SAVE).unwrap_or_default();
+        let fields = parse_fields(&contents);
+
+        let mut splits = Self {
+            unlocked: field(&fields, "unlocked").unwrap_or(0),
+            ..default()
+        };
+        for (cycle, time) in splits.times.iter_mut().enumerate() {
+            *time = field(&fields, &format!("cycle_{}", cycle));
+        }
+
+        splits
+    }
+
+    pub fn save(&self) {
+        let mut contents = format!("unlocked {}\n", self.unlocked);
+        for (cycle, time) in self.times.iter().enumerate() {
+            if let Some(time) = time {
+                contents += &format!("cycle_{} {}\n", cycle, time);
+            }
+        }
+
+        write_save(SPLITS_SAVE, &contents);
+    }
+
+    pub fn best(&self, cycle: u8) -> Option<f32> {
+        self.times.get(cycle as usize).copied().flatten()
+    }
+
+    /// Keeps the faster of the recorded and the new split of every cycle.
+    pub fn record(&mut self, splits: &[Split]) {
+        for split in splits {
+            if let Some(best) = self.times.get_mut(split.cycle as usize) {
+                if best.map_or(true, |best| split.time < best) {
+                    *best = Some(split.time);
+                }
+            }
+        }
+    }
+
+    /// Time lost against the best splits, negative when ahead. The running split only counts
+    /// once it is behind, `None` until there is a best split to compare against.
+    pub fn delta(&self, splits: &[Split], current: Split) -> Option<f32> {
+        let mut delta = None;
+
+        for split in splits {
+            if let Some(best) = self.best(split.cycle) {
+                *delta.get_or_insert(0.0) += split.time - best;
+            }
+        }
+
+        if let Some(best) = self.best(current.cycle) {
+            *delta.get_or_insert(0.0) += (current.time - best).max(0.0);
+        }
+
+        delta
+    }
+}
+
+// GAMEPLAY VARIABLES
+
+/// Teleports it takes to finish the story.
+pub(crate) const STORY_CYCLES: u8 = 8;
+
+const AHEAD_COLOR: Color = Color::rgb(0.2, 0.9, 0.2);
+const BEHIND_COLOR: Color = Color::rgb(0.9, 0.15, 0.15);
+
+// Systems
+
+fn sys_load_splits(mut commands: Commands) {
+    commands.insert_resource(BestSplits::load());
+}
+
+/// Every cycle reached in the story unlocks it as a time attack start.
+fn sys_unlock_cycles(state: Res<FallingRun>, mut best_splits: ResMut<BestSplits>) {
+    if state.mode == RunMode::Endless || state.cycle_number >= STORY_CYCLES {
+        return;
+    }
+
+    if state.cycle_number > best_splits.unlocked {
+        best_splits.unlocked = state.cycle_number;
+        // The best times are untouched since they were loaded, see `sys_load_splits`
+        best_splits.save();
+    }
+}
+
+fn sys_update_split_text(
+    state: Res<FallingRun>,
+    best_splits: Res<BestSplits>,
+    mut query_text: Query<&mut Text, With<SplitText>>,
+) {
+    let delta = best_splits.delta(&state.splits, state.current_split());
+
+    for mut text in query_text.iter_mut() {
+        let section = &mut text.sections[0];
+
+        match delta {
+            Some(delta) => {
+                section.value = format!("split   {:+.2}", delta);
+                section.style.color = match delta <= 0.0 {
+                    true => AHEAD_COLOR,
+                    false => BEHIND_COLOR,
+                };
+            }
+            None => {
+                section.value = format!("split   {:.2}", state.current_split().time);
+                section.style.color = Color::WHITE;
+            }
+        }
+    }
+}
+
+fn sys_end_splits(mut commands: Commands) {
+    commands.remove_resource::<BestSplits>();
+}
+
+// Plugins
+
+pub struct TimeAttackPlugin;
+impl Plugin for TimeAttackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::FallingGame).with_system(sys_load_splits))
+            .add_system_set(
+                SystemSet::on_update(AppState::FallingGame)
+                    .with_system(sys_unlock_cycles)
+                    .with_system(sys_update_split_text),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::FallingGame).with_system(sys_end_splits));
+    }
+}
@@ -0,0 +1,695 @@
+use bevy::prelude::*;
+
+use crate::audio::*;
+use crate::endless::*;
+use crate::main_menu::MenuPage;
+
+// Components
+
+/// What a widget does when it is activated, handled by the screen that spawned it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MenuAction {
+    Play(RunMode),
+    /// Runs again with the same options.
+    Restart,
+    ContinueFromCheckpoint,
+    MainMenu,
+    NextStartCycle,
+    /// Opens a page of the main menu, or closes it if it is already open.
+    OpenPage(MenuPage),
+    Quit,
+    MusicVolume,
+    SoundVolume,
+    VoiceVolume,
+    ScreenShake,
+    Fullscreen,
+}
+
+#[derive(Component)]
+pub(crate) struct MenuButton {
+    pub action: MenuAction,
+}
+
+#[derive(Component)]
+pub(crate) struct Toggle {
+    pub action: MenuAction,
+    pub label: String,
+    pub on: bool,
+}
+
+#[derive(Component)]
+pub(crate) struct Slider {
+    pub action: MenuAction,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+#[derive(Component, Default)]
+pub struct SliderFill;
+
+/// Reachable with the keyboard and gamepad, in reading order.
+#[derive(Component, Default)]
+pub struct Focusable;
+
+#[derive(Clone, Copy)]
+pub(crate) enum WidgetSize {
+    Large,
+    Small,
+}
+
+impl WidgetSize {
+    fn height(&self) -> f32 {
+        match self {
+            WidgetSize::Large => 65.0,
+            WidgetSize::Small => 35.0,
+        }
+    }
+
+    fn font_size(&self) -> f32 {
+        match self {
+            WidgetSize::Large => 20.0,
+            WidgetSize::Small => 14.0,
+        }
+    }
+}
+
+/// A row of a list, dimmed rows are the ones that don't apply yet.
+pub(crate) struct ListItem {
+    pub title: String,
+    pub detail: String,
+    pub dimmed: bool,
+}
+
+// Resources
+
+#[derive(Default)]
+pub(crate) struct Focus {
+    pub entity: Option<Entity>,
+}
+
+// Events
+
+pub(crate) struct ButtonActivated(pub MenuAction);
+
+pub(crate) struct ToggleChanged {
+    pub action: MenuAction,
+    pub on: bool,
+}
+
+pub(crate) struct SliderChanged {
+    pub action: MenuAction,
+    pub value: f32,
+}
+
+// Theme
+
+pub(crate) const FONT: &str = "fonts/ARCADECLASSIC.TTF";
+pub(crate) const NORMAL_BUTTON: Color = Color::rgb(0.65, 0.15, 0.15);
+pub(crate) const HOVERED_BUTTON: Color = Color::rgb(0.75, 0.25, 0.25);
+pub(crate) const PRESSED_BUTTON: Color = Color::rgb(1.0, 0.35, 0.25);
+pub(crate) const PANEL_COLOR: Color = Color::rgba(0.1, 0.0, 0.0, 0.85);
+const DIMMED_TEXT: Color = Color::rgb(0.4, 0.4, 0.4);
+const WIDGET_WIDTH: f32 = 170.0;
+
+// Widgets
+
+pub(crate) fn panel_bundle() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            padding: Rect::all(Val::Px(20.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: PANEL_COLOR.into(),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn label_bundle(
+    asset_server: &AssetServer,
+    value: impl Into<String>,
+    font_size: f32,
+    color: Color,
+) -> TextBundle {
+    TextBundle {
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: asset_server.load(FONT),
+                font_size,
+                color,
+            },
+            TextAlignment {
+                horizontal: HorizontalAlign::Center,
+                ..Default::default()
+            },
+        ),
+        ..Default::default()
+    }
+}
+
+fn widget_bundle(size: WidgetSize) -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(WIDGET_WIDTH), Val::Px(size.height())),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: Rect::all(Val::Px(5.0)),
+            ..Default::default()
+        },
+        color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn spawn_button(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    label: impl Into<String>,
+    action: MenuAction,
+    size: WidgetSize,
+) -> Entity {
+    parent
+        .spawn_bundle(widget_bundle(size))
+        .insert(MenuButton { action })
+        .insert(Focusable)
+        .with_children(|parent| {
+            parent.spawn_bundle(label_bundle(
+                asset_server,
+                label,
+                size.font_size(),
+                Color::WHITE,
+            ));
+        })
+        .id()
+}
+
+pub(crate) fn spawn_toggle(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    label: impl Into<String>,
+    action: MenuAction,
+    on: bool,
+) -> Entity {
+    let label = label.into();
+
+    parent
+        .spawn_bundle(widget_bundle(WidgetSize::Small))
+        .insert(Focusable)
+        .with_children(|parent| {
+            parent.spawn_bundle(label_bundle(
+                asset_server,
+                toggle_label(&label, on),
+                WidgetSize::Small.font_size(),
+                Color::WHITE,
+            ));
+        })
+        .insert(Toggle { action, label, on })
+        .id()
+}
+
+fn toggle_label(label: &str, on: bool) -> String {
+    format!("{}  {}", label, if on { "ON" } else { "OFF" })
+}
+
+pub(crate) fn spawn_slider(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    label: impl Into<String>,
+    slider: Slider,
+) -> Entity {
+    parent.spawn_bundle(label_bundle(
+        asset_server,
+        label,
+        WidgetSize::Small.font_size(),
+        Color::WHITE,
+    ));
+
+    let fill = slider_share(&slider) * 100.0;
+
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(WIDGET_WIDTH), Val::Px(12.0)),
+                margin: Rect::all(Val::Px(5.0)),
+                ..Default::default()
+            },
+            color: NORMAL_BUTTON.into(),
+            ..Default::default()
+        })
+        .insert(slider)
+        .insert(Focusable)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(fill), Val::Percent(100.0)),
+                        ..Default::default()
+                    },
+                    color: Color::WHITE.into(),
+                    ..Default::default()
+                })
+                .insert(SliderFill);
+        })
+        .id()
+}
+
+fn slider_share(slider: &Slider) -> f32 {
+    ((slider.value - slider.min) / (slider.max - slider.min)).clamp(0.0, 1.0)
+}
+
+pub(crate) fn spawn_list(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    items: impl IntoIterator<Item = ListItem>,
+) -> Entity {
+    parent
+        .spawn_bundle(panel_bundle())
+        .with_children(|parent| {
+            for item in items {
+                let color = match item.dimmed {
+                    true => DIMMED_TEXT,
+                    false => Color::WHITE,
+                };
+
+                parent.spawn_bundle(label_bundle(asset_server, item.title, 25.0, color));
+
+                let mut detail = label_bundle(asset_server, item.detail, 14.0, color);
+                detail.style.margin.bottom = Val::Px(12.0);
+                parent.spawn_bundle(detail);
+            }
+        })
+        .id()
+}
+
+// Systems
+
+fn pressed(
+    keys: &Input<KeyCode>,
+    key_codes: &[KeyCode],
+    gamepads: &Gamepads,
+    buttons: &Input<GamepadButton>,
+    button_types: &[GamepadButtonType],
+) -> bool {
+    key_codes.iter().any(|key| keys.just_pressed(*key))
+        || gamepads.iter().any(|gamepad| {
+            button_types
+                .iter()
+                .any(|button| buttons.just_pressed(GamepadButton(*gamepad, *button)))
+        })
+}
+
+/// Activates a widget, sliders are pushed a step up and wrap around.
+fn activate(
+    entity: Entity,
+    query_widgets: &mut Query<(
+        Option<&MenuButton>,
+        Option<&mut Toggle>,
+        Option<&mut Slider>,
+    )>,
+    buttons: &mut EventWriter<ButtonActivated>,
+    toggles: &mut EventWriter<ToggleChanged>,
+    sliders: &mut EventWriter<SliderChanged>,
+) {
+    match query_widgets.get_mut(entity) {
+        Ok((Some(button), _, _)) => buttons.send(ButtonActivated(button.action)),
+        Ok((_, Some(mut toggle), _)) => {
+            toggle.on = !toggle.on;
+            toggles.send(ToggleChanged {
+                action: toggle.action,
+                on: toggle.on,
+            });
+        }
+        Ok((_, _, Some(mut slider))) => {
+            slider.value = match slider.value >= slider.max {
+                true => slider.min,
+                false => (slider.value + slider.step).min(slider.max),
+            };
+            sliders.send(SliderChanged {
+                action: slider.action,
+                value: slider.value,
+            });
+        }
+        _ => {}
+    }
+}
+
+fn sys_navigate_focus(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut focus: ResMut<Focus>,
+    mut sounds: EventWriter<PlaySound>,
+    mut buttons: EventWriter<ButtonActivated>,
+    mut toggles: EventWriter<ToggleChanged>,
+    mut sliders: EventWriter<SliderChanged>,
+    query_focusable: Query<(Entity, &GlobalTransform), With<Focusable>>,
+    mut query_widgets: Query<(
+        Option<&MenuButton>,
+        Option<&mut Toggle>,
+        Option<&mut Slider>,
+    )>,
+) {
+    use GamepadButtonType::*;
+
+    // Reading order, UI space has y going up
+    let mut focusable: Vec<(Entity, Vec3)> = query_focusable
+        .iter()
+        .map(|(e, transform)| (e, transform.translation))
+        .collect();
+    focusable.sort_by(|(_, a), (_, b)| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    // The focused widget may be gone with the page it was on
+    let current = focus
+        .entity
+        .and_then(|focused| focusable.iter().position(|(e, _)| *e == focused));
+    if current.is_none() {
+        focus.entity = None;
+    }
+
+    let slider_focused = focus.entity.map_or(false, |e| {
+        matches!(query_widgets.get(e), Ok((_, _, Some(_))))
+    });
+
+    let up = pressed(
+        &keys,
+        &[KeyCode::Up, KeyCode::W],
+        &gamepads,
+        &gamepad_buttons,
+        &[DPadUp],
+    );
+    let down = pressed(
+        &keys,
+        &[KeyCode::Down, KeyCode::S, KeyCode::Tab],
+        &gamepads,
+        &gamepad_buttons,
+        &[DPadDown],
+    );
+    let left = pressed(
+        &keys,
+        &[KeyCode::Left, KeyCode::A],
+        &gamepads,
+        &gamepad_buttons,
+        &[DPadLeft],
+    );
+    let right = pressed(
+        &keys,
+        &[KeyCode::Right, KeyCode::D],
+        &gamepads,
+        &gamepad_buttons,
+        &[DPadRight],
+    );
+    let confirm = pressed(
+        &keys,
+        &[KeyCode::Return, KeyCode::Space],
+        &gamepads,
+        &gamepad_buttons,
+        &[South],
+    );
+
+    // Left and right move the focused slider, anywhere else they step like up and down
+    if slider_focused && (left || right) {
+        let focused = focus.entity.unwrap();
+        if let Ok((_, _, Some(mut slider))) = query_widgets.get_mut(focused) {
+            let step = if right { slider.step } else { -slider.step };
+            slider.value = (slider.value + step).clamp(slider.min, slider.max);
+            sliders.send(SliderChanged {
+                action: slider.action,
+                value: slider.value,
+            });
+            sounds.send(PlaySound::ui("music/hover.mp3"));
+        }
+        return;
+    }
+
+    let step: isize = match (up || left, down || right) {
+        (true, false) => -1,
+        (false, true) => 1,
+        _ => 0,
+    };
+
+    if step != 0 && !focusable.is_empty() {
+        let len = focusable.len() as isize;
+        let next = match current {
+            Some(i) => (i as isize + step).rem_euclid(len),
+            None if step > 0 => 0,
+            None => len - 1,
+        };
+        focus.entity = Some(focusable[next as usize].0);
+        sounds.send(PlaySound::ui("music/hover.mp3"));
+    }
+
+    if confirm {
+        if let Some(focused) = focus.entity {
+            sounds.send(PlaySound::ui("music/click.mp3"));
+            activate(
+                focused,
+                &mut query_widgets,
+                &mut buttons,
+                &mut toggles,
+                &mut sliders,
+            );
+        }
+    }
+}
+
+fn sys_pointer_interaction(
+    windows: Res<Windows>,
+    mut focus: ResMut<Focus>,
+    mut sounds: EventWriter<PlaySound>,
+    mut buttons: EventWriter<ButtonActivated>,
+    mut toggles: EventWriter<ToggleChanged>,
+    mut sliders: EventWriter<SliderChanged>,
+    query_interaction: Query<
+        (Entity, &Interaction, &Node, &GlobalTransform),
+        (Changed<Interaction>, With<Focusable>),
+    >,
+    mut query_widgets: Query<(
+        Option<&MenuButton>,
+        Option<&mut Toggle>,
+        Option<&mut Slider>,
+    )>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+
+    for (e, interaction, node, transform) in query_interaction.iter() {
+        match *interaction {
+            Interaction::Clicked => {
+                sounds.send(PlaySound::ui("music/click.mp3"));
+                focus.entity = Some(e);
+
+                // Sliders jump to where they were clicked
+                if let (Ok((_, _, Some(mut slider))), Some(cursor)) =
+                    (query_widgets.get_mut(e), cursor)
+                {
+                    let left = transform.translation.x - node.size.x / 2.0;
+                    let share = ((cursor.x - left) / node.size.x).clamp(0.0, 1.0);
+                    let value = slider.min + share * (slider.max - slider.min);
+                    slider.value =
+                        ((value / slider.step).round() * slider.step).clamp(slider.min, slider.max);
+                    sliders.send(SliderChanged {
+                        action: slider.action,
+                        value: slider.value,
+                    });
+                    continue;
+                }
+
+                activate(
+                    e,
+                    &mut query_widgets,
+                    &mut buttons,
+                    &mut toggles,
+                    &mut sliders,
+                );
+            }
+            Interaction::Hovered => {
+                if focus.entity != Some(e) {
+                    sounds.send(PlaySound::ui("music/hover.mp3"));
+                }
+                focus.entity = Some(e);
+            }
+            Interaction::None => {}
+        }
+    }
+}
+
+fn sys_widget_colors(
+    focus: Res<Focus>,
+    mut query_widgets: Query<(Entity, &Interaction, &mut UiColor), With<Focusable>>,
+) {
+    for (e, interaction, mut color) in query_widgets.iter_mut() {
+        let target = match (*interaction, focus.entity == Some(e)) {
+            (Interaction::Clicked, _) => PRESSED_BUTTON,
+            (Interaction::Hovered, _) | (_, true) => HOVERED_BUTTON,
+            _ => NORMAL_BUTTON,
+        };
+
+        if color.0 != target {
+            *color = target.into();
+        }
+    }
+}
+
+fn sys_update_toggle_labels(
+    query_toggles: Query<(&Toggle, &Children), Changed<Toggle>>,
+    mut query_text: Query<&mut Text>,
+) {
+    for (toggle, children) in query_toggles.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = query_text.get_mut(*child) {
+                text.sections[0].value = toggle_label(&toggle.label, toggle.on);
+            }
+        }
+    }
+}
+
+fn sys_update_slider_fills(
+    query_sliders: Query<(&Slider, &Children), Changed<Slider>>,
+    mut query_fills: Query<&mut Style, With<SliderFill>>,
+) {
+    for (slider, children) in query_sliders.iter() {
+        for child in children.iter() {
+            if let Ok(mut style) = query_fills.get_mut(*child) {
+                style.size.width = Val::Percent(slider_share(slider) * 100.0);
+            }
+        }
+    }
+}
+
+// Plugins
+
+pub struct WidgetsPlugin;
+impl Plugin for WidgetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Focus>()
+            .add_event::<ButtonActivated>()
+            .add_event::<ToggleChanged>()
+            .add_event::<SliderChanged>()
+            .add_system(sys_navigate_focus)
+            .add_system(sys_pointer_interaction)
+            .add_system(sys_widget_colors)
+            .add_system(sys_update_toggle_labels)
+            .add_system(sys_update_slider_fills);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::Events;
+
+    fn new_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Gamepads>()
+            .init_resource::<Input<GamepadButton>>()
+            .add_event::<PlaySound>()
+            .init_resource::<Focus>()
+            .add_event::<ButtonActivated>()
+            .add_event::<ToggleChanged>()
+            .add_event::<SliderChanged>()
+            .add_system(sys_navigate_focus);
+        app
+    }
+
+    fn spawn_button_at(app: &mut App, x: f32, y: f32, action: MenuAction) -> Entity {
+        app.world
+            .spawn()
+            .insert(GlobalTransform::from_xyz(x, y, 0.0))
+            .insert(Focusable)
+            .insert(MenuButton { action })
+            .id()
+    }
+
+    fn press(app: &mut App, key: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().press(key);
+        app.update();
+
+        let mut keys = app.world.resource_mut::<Input<KeyCode>>();
+        keys.release(key);
+        keys.clear();
+    }
+
+    fn focused(app: &App) -> Option<Entity> {
+        app.world.resource::<Focus>().entity
+    }
+
+    #[test]
+    fn focus_moves_in_reading_order_and_wraps() {
+        let mut app = new_app();
+        let top = spawn_button_at(&mut app, 0.0, 100.0, MenuAction::Restart);
+        let right = spawn_button_at(&mut app, 10.0, 50.0, MenuAction::MainMenu);
+        let left = spawn_button_at(&mut app, -10.0, 50.0, MenuAction::Quit);
+
+        press(&mut app, KeyCode::Down);
+        assert_eq!(focused(&app), Some(top));
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(focused(&app), Some(left));
+        press(&mut app, KeyCode::Right);
+        assert_eq!(focused(&app), Some(right));
+        press(&mut app, KeyCode::Down);
+        assert_eq!(focused(&app), Some(top));
+        press(&mut app, KeyCode::Up);
+        assert_eq!(focused(&app), Some(right));
+    }
+
+    #[test]
+    fn focus_forgets_despawned_widgets() {
+        let mut app = new_app();
+        let top = spawn_button_at(&mut app, 0.0, 100.0, MenuAction::Restart);
+        let bottom = spawn_button_at(&mut app, 0.0, 50.0, MenuAction::MainMenu);
+
+        press(&mut app, KeyCode::Down);
+        assert_eq!(focused(&app), Some(top));
+
+        app.world.despawn(top);
+        app.update();
+        assert_eq!(focused(&app), None);
+
+        press(&mut app, KeyCode::Up);
+        assert_eq!(focused(&app), Some(bottom));
+    }
+
+    #[test]
+    fn confirm_activates_focused_widget() {
+        let mut app = new_app();
+        spawn_button_at(&mut app, 0.0, 100.0, MenuAction::Restart);
+        let slider = app
+            .world
+            .spawn()
+            .insert(GlobalTransform::from_xyz(0.0, 50.0, 0.0))
+            .insert(Focusable)
+            .insert(Slider {
+                action: MenuAction::MusicVolume,
+                value: 0.5,
+                min: 0.0,
+                max: 1.0,
+                step: 0.1,
+            })
+            .id();
+
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Return);
+        let events = app.world.resource::<Events<ButtonActivated>>();
+        let activated: Vec<MenuAction> = events
+            .get_reader()
+            .iter(events)
+            .map(|ButtonActivated(action)| *action)
+            .collect();
+        assert_eq!(activated, vec![MenuAction::Restart]);
+
+        // Left and right move a focused slider instead of the focus
+        press(&mut app, KeyCode::Down);
+        assert_eq!(focused(&app), Some(slider));
+        press(&mut app, KeyCode::Left);
+        assert_eq!(focused(&app), Some(slider));
+        let value = app.world.get::<Slider>(slider).unwrap().value;
+        assert!((value - 0.4).abs() < 1e-6);
+    }
+}